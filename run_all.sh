@@ -1,133 +1,10 @@
 export RUSTFLAGS="-C target-cpu=native"
 
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst
-
-echo "############################################ N = 0"
-######## N = 0
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 512 -o mainnet_512b.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_512b.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 1024 -o mainnet_1k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_1k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 2048 -o mainnet_2k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_2k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 4096 -o mainnet_4k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_4k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 8192 -o mainnet_8k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_8k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 16384 -o mainnet_16k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_16k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 32768 -o mainnet_32k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_32k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 65536 -o mainnet_64k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_64k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 131072 -o mainnet_128k.bin  -n 0
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_128k.bin
-
-######## N = 1
-echo "############################################ N = 1"
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 512 -o mainnet_512b.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_512b.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 1024 -o mainnet_1k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_1k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 2048 -o mainnet_2k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_2k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 4096 -o mainnet_4k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_4k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 8192 -o mainnet_8k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_8k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 16384 -o mainnet_16k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_16k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 32768 -o mainnet_32k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_32k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 65536 -o mainnet_64k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_64k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 131072 -o mainnet_128k.bin  -n 1
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_128k.bin
-
-echo "############################################ N = 2"
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 512 -o mainnet_512b.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_512b.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 1024 -o mainnet_1k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_1k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 2048 -o mainnet_2k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_2k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 4096 -o mainnet_4k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_4k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 8192 -o mainnet_8k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_8k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 16384 -o mainnet_16k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_16k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 32768 -o mainnet_32k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_32k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 65536 -o mainnet_64k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_64k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 131072 -o mainnet_128k.bin  -n 2
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_128k.bin
-
-
-echo "############################################ N = 3"
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 512 -o mainnet_512b.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_512b.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 1024 -o mainnet_1k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_1k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 2048 -o mainnet_2k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_2k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 4096 -o mainnet_4k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_4k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 8192 -o mainnet_8k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_8k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 16384 -o mainnet_16k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_16k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 32768 -o mainnet_32k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_32k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 65536 -o mainnet_64k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_64k.bin
-
-cargo run --release --bin solana-accountsdb-dictionary-creator -- -a mainnet.tar.zst -d 131072 -o mainnet_128k.bin  -n 3
-cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -d mainnet_128k.bin
-
-
-###### without dictionary
-
-# cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -m 10000000
-
-# cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -s 1 
-
-# cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -s 3
-
-# cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -s 8
-
-# cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -s 10
-
-# cargo run --release --bin solana-accountsdb-dictionary-tester -- -a mainnet.tar.zst -s 100
\ No newline at end of file
+# reads the snapshot once and evaluates every combination of owner prefix bits, dictionary sizes,
+# codecs and levels, see --help for the grid
+cargo run --release --bin solana-accountsdb-dictionary-sweep -- -a mainnet.tar.zst \
+    -n 0,1,2,3,8 \
+    -d 512,1024,2048,4096,8192,16384,32768,65536,131072 \
+    -c lz4,lz4-flex,zstd \
+    -l 1,3 \
+    -o mainnet_sweep.csv
@@ -78,6 +78,9 @@ pub struct AppendVec {
     file_size: u64,
 
     slot: u64,
+
+    /// Distinguishes the AppendVecs of a slot.
+    id: u64,
 }
 
 impl AppendVec {
@@ -126,6 +129,7 @@ impl AppendVec {
         path: P,
         current_len: usize,
         slot: u64,
+        id: u64,
     ) -> io::Result<Self> {
         let data = OpenOptions::new()
             .read(true)
@@ -150,6 +154,7 @@ impl AppendVec {
             current_len,
             file_size,
             slot,
+            id,
         };
 
         Ok(new)
@@ -159,6 +164,7 @@ impl AppendVec {
         reader: &mut R,
         current_len: usize,
         slot: u64,
+        id: u64,
     ) -> io::Result<Self> {
         let mut map = MmapMut::map_anon(current_len)?;
         io::copy(&mut reader.take(current_len as u64), &mut map.as_mut())?;
@@ -167,6 +173,7 @@ impl AppendVec {
             current_len,
             file_size: current_len as u64,
             slot,
+            id,
         })
     }
 
@@ -226,4 +233,171 @@ impl AppendVec {
     pub const fn slot(&self) -> u64 {
         self.slot
     }
+
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Copies the accounts matching `predicate` into a new in-memory AppendVec of the same slot
+    /// and id.
+    /// Returns `None` if no account matches.
+    pub fn filter<F>(&self, mut predicate: F) -> io::Result<Option<Self>>
+    where
+        F: FnMut(&StoredAccountMeta) -> bool,
+    {
+        // (offset, end) of the kept accounts, the last account is not necessarily padded
+        let mut kept = Vec::new();
+        let mut offset = 0;
+        while let Some((account, next)) = self.get_account(offset) {
+            if predicate(&account) {
+                kept.push((offset, next.min(self.len())));
+            }
+            offset = next;
+        }
+        if kept.is_empty() {
+            return Ok(None);
+        }
+
+        let capacity = kept
+            .iter()
+            .map(|(start, end)| u64_align!(end - start))
+            .sum();
+        let mut map = MmapMut::map_anon(capacity)?;
+        let mut current_len = 0;
+        for (start, end) in kept {
+            let current_offset = u64_align!(current_len);
+            map[current_offset..current_offset + end - start]
+                .copy_from_slice(&self.map[start..end]);
+            current_len = current_offset + end - start;
+        }
+        Ok(Some(AppendVec {
+            map: map.make_read_only()?,
+            current_len,
+            file_size: capacity as u64,
+            slot: self.slot,
+            id: self.id,
+        }))
+    }
+}
+
+/// Builds an in-memory AppendVec from `(pubkey, write_version, lamports, owner, data)` tuples.
+#[cfg(test)]
+pub(crate) fn test_append_vec(
+    slot: u64,
+    id: u64,
+    accounts: &[(
+        solana_sdk::pubkey::Pubkey,
+        u64,
+        u64,
+        solana_sdk::pubkey::Pubkey,
+        &[u8],
+    )],
+) -> AppendVec {
+    let bytes = test_append_vec_bytes(accounts);
+    AppendVec::new_from_reader(&mut &bytes[..], bytes.len(), slot, id).unwrap()
+}
+
+/// AppendVec of `accounts` new accounts of `owner` holding `data`, with write versions counting
+/// from zero.
+#[cfg(test)]
+pub(crate) fn test_owner_append_vec(
+    slot: u64,
+    accounts: u64,
+    owner: solana_sdk::pubkey::Pubkey,
+    data: &[u8],
+) -> AppendVec {
+    let accounts = (0..accounts)
+        .map(|write_version| {
+            (
+                solana_sdk::pubkey::Pubkey::new_unique(),
+                write_version,
+                1,
+                owner,
+                data,
+            )
+        })
+        .collect::<Vec<_>>();
+    test_append_vec(slot, 0, &accounts)
+}
+
+/// Content of the AppendVec file built by [`test_append_vec`].
+#[cfg(test)]
+pub(crate) fn test_append_vec_bytes(
+    accounts: &[(
+        solana_sdk::pubkey::Pubkey,
+        u64,
+        u64,
+        solana_sdk::pubkey::Pubkey,
+        &[u8],
+    )],
+) -> Vec<u8> {
+    const fn as_bytes<T>(value: &T) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+    }
+
+    let mut bytes = Vec::new();
+    for (pubkey, write_version, lamports, owner, data) in accounts {
+        bytes.resize(u64_align!(bytes.len()), 0);
+        let meta = StoredMeta {
+            write_version_obsolete: *write_version,
+            data_len: data.len() as u64,
+            pubkey: *pubkey,
+        };
+        let account_meta = AccountMeta {
+            lamports: *lamports,
+            rent_epoch: 0,
+            owner: *owner,
+            executable: false,
+        };
+        bytes.extend_from_slice(as_bytes(&meta));
+        bytes.extend_from_slice(as_bytes(&account_meta));
+        bytes.extend_from_slice(as_bytes(&Hash::default()));
+        bytes.extend_from_slice(data);
+    }
+    bytes
+}
+
+#[test]
+fn test_filter() {
+    use solana_sdk::pubkey::Pubkey;
+
+    let owner = Pubkey::new_unique();
+    let accounts = (0..5u8)
+        .map(|i| {
+            (
+                Pubkey::new_unique(),
+                i as u64,
+                1,
+                owner,
+                vec![i; i as usize * 3],
+            )
+        })
+        .collect::<Vec<_>>();
+    let accounts = accounts
+        .iter()
+        .map(|(pubkey, write_version, lamports, owner, data)| {
+            (*pubkey, *write_version, *lamports, *owner, &data[..])
+        })
+        .collect::<Vec<_>>();
+    let append_vec = test_append_vec(42, 7, &accounts);
+
+    let filtered = append_vec
+        .filter(|account| account.meta.write_version_obsolete % 2 == 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!((filtered.slot(), filtered.id()), (42, 7));
+    let mut offset = 0;
+    let mut kept = Vec::new();
+    while let Some((account, next)) = filtered.get_account(offset) {
+        kept.push((account.meta.pubkey, account.data.to_vec()));
+        offset = next;
+    }
+    let expected = accounts
+        .iter()
+        .filter(|account| account.1 % 2 == 0)
+        .map(|account| (account.0, account.4.to_vec()))
+        .collect::<Vec<_>>();
+    assert_eq!(kept, expected);
+
+    assert!(append_vec.filter(|_| false).unwrap().is_none());
 }
@@ -1,28 +1,120 @@
 use {
     crate::{
-        deserialize_from, parse_append_vec_name, AccountsDbFields, AppendVec, AppendVecIterator,
-        DeserializableVersionedBank, SerializableAccountStorageEntry, SnapshotError,
-        SnapshotExtractor, SnapshotResult,
+        deserialize_from, manifest::SnapshotManifest, parse_append_vec_name, AccountsDbFields,
+        AppendVec, AppendVecIterator, DeserializableVersionedBank, ReadProgressTracking,
+        SerializableAccountStorageEntry, SnapshotError, SnapshotExtractor, SnapshotResult,
     },
     log::info,
+    solana_runtime::snapshot_utils::ArchiveFormat,
     std::{
         fs::File,
-        io::{BufReader, Read},
-        path::{Component, Path},
+        io::{BufRead, BufReader, BufWriter, Read, Write},
+        path::{Component, Path, PathBuf},
         pin::Pin,
         time::Instant,
     },
     tar::{Archive, Entries, Entry},
+    tempfile::TempDir,
 };
 
-/// Extracts account data from a .tar.zst stream.
+/// Decompresses a snapshot archive stream according to its `ArchiveFormat`.
+pub enum ArchiveDecoder<Source>
+where
+    Source: Read,
+{
+    Zstd(zstd::Decoder<'static, BufReader<Source>>),
+    Gzip(flate2::read::GzDecoder<BufReader<Source>>),
+    Bzip2(bzip2::read::BzDecoder<BufReader<Source>>),
+    Lz4(lz4::Decoder<BufReader<Source>>),
+    Tar(BufReader<Source>),
+}
+
+impl<Source> ArchiveDecoder<Source>
+where
+    Source: Read,
+{
+    pub fn new(source: Source, archive_format: ArchiveFormat) -> SnapshotResult<Self> {
+        Self::from_buf_reader(BufReader::new(source), archive_format)
+    }
+
+    fn from_buf_reader(
+        source: BufReader<Source>,
+        archive_format: ArchiveFormat,
+    ) -> SnapshotResult<Self> {
+        Ok(match archive_format {
+            ArchiveFormat::TarZstd => Self::Zstd(zstd::Decoder::with_buffer(source)?),
+            ArchiveFormat::TarGzip => Self::Gzip(flate2::read::GzDecoder::new(source)),
+            ArchiveFormat::TarBzip2 => Self::Bzip2(bzip2::read::BzDecoder::new(source)),
+            ArchiveFormat::TarLz4 => Self::Lz4(lz4::Decoder::new(source)?),
+            ArchiveFormat::Tar => Self::Tar(source),
+        })
+    }
+}
+
+impl<Source> Read for ArchiveDecoder<Source>
+where
+    Source: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Zstd(decoder) => decoder.read(buf),
+            Self::Gzip(decoder) => decoder.read(buf),
+            Self::Bzip2(decoder) => decoder.read(buf),
+            Self::Lz4(decoder) => decoder.read(buf),
+            Self::Tar(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Detects the archive format from the file extension, e.g. `.tar.zst`.
+pub fn archive_format_from_path(path: &Path) -> Option<ArchiveFormat> {
+    let file_name = path.file_name()?.to_str()?;
+    [
+        ArchiveFormat::TarZstd,
+        ArchiveFormat::TarGzip,
+        ArchiveFormat::TarBzip2,
+        ArchiveFormat::TarLz4,
+        ArchiveFormat::Tar,
+    ]
+    .into_iter()
+    .find(|format| file_name.ends_with(&format!(".{}", format.extension())))
+}
+
+/// Detects the archive format from the first bytes of the stream, anything that is not a
+/// known compressed format is assumed to be a plain tar.
+pub fn archive_format_from_magic_bytes(header: &[u8]) -> ArchiveFormat {
+    const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+    const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+    const BZIP2_MAGIC: &[u8] = b"BZh";
+    const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+    if header.starts_with(ZSTD_MAGIC) {
+        ArchiveFormat::TarZstd
+    } else if header.starts_with(GZIP_MAGIC) {
+        ArchiveFormat::TarGzip
+    } else if header.starts_with(BZIP2_MAGIC) {
+        ArchiveFormat::TarBzip2
+    } else if header.starts_with(LZ4_MAGIC) {
+        ArchiveFormat::TarLz4
+    } else {
+        ArchiveFormat::Tar
+    }
+}
+
+/// Extracts account data from a snapshot archive stream.
+///
+/// AppendVecs found before the snapshot manifest are spilled to a temporary directory
+/// and yielded first once the manifest has been read.
 pub struct ArchiveSnapshotExtractor<Source>
 where
     Source: Read + Unpin + 'static,
 {
     accounts_db_fields: AccountsDbFields<SerializableAccountStorageEntry>,
-    _archive: Pin<Box<Archive<zstd::Decoder<'static, BufReader<Source>>>>>,
-    entries: Option<Entries<'static, zstd::Decoder<'static, BufReader<Source>>>>,
+    manifest: SnapshotManifest,
+    archive_format: ArchiveFormat,
+    _archive: Pin<Box<Archive<ArchiveDecoder<Source>>>>,
+    entries: Option<Entries<'static, ArchiveDecoder<Source>>>,
+    spilled_append_vecs: Vec<(u64, u64, PathBuf)>,
+    _spill_dir: Option<TempDir>,
 }
 
 impl<Source> SnapshotExtractor for ArchiveSnapshotExtractor<Source>
@@ -32,14 +124,40 @@ where
     fn iter(&mut self) -> AppendVecIterator<'_> {
         Box::new(self.unboxed_iter())
     }
+
+    fn accounts_db_fields(&self) -> &AccountsDbFields<SerializableAccountStorageEntry> {
+        &self.accounts_db_fields
+    }
+
+    fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
 }
 
 impl<Source> ArchiveSnapshotExtractor<Source>
 where
     Source: Read + Unpin + 'static,
 {
+    /// Opens an archive stream, detecting its format from the magic bytes.
     pub fn from_reader(source: Source) -> SnapshotResult<Self> {
-        let tar_stream = zstd::stream::read::Decoder::new(source)?;
+        let mut source = BufReader::new(source);
+        let archive_format = archive_format_from_magic_bytes(source.fill_buf()?);
+        let tar_stream = ArchiveDecoder::from_buf_reader(source, archive_format)?;
+        Self::from_decoder(tar_stream, archive_format)
+    }
+
+    pub fn from_reader_with_format(
+        source: Source,
+        archive_format: ArchiveFormat,
+    ) -> SnapshotResult<Self> {
+        Self::from_decoder(ArchiveDecoder::new(source, archive_format)?, archive_format)
+    }
+
+    fn from_decoder(
+        tar_stream: ArchiveDecoder<Source>,
+        archive_format: ArchiveFormat,
+    ) -> SnapshotResult<Self> {
+        info!("Reading {} snapshot archive", archive_format.extension());
         let mut archive = Box::pin(Archive::new(tar_stream));
 
         // This is safe as long as we guarantee that entries never gets accessed past drop.
@@ -48,17 +166,35 @@ where
 
         // Search for snapshot manifest.
         let mut snapshot_file: Option<Entry<_>> = None;
+        let mut spill_dir: Option<TempDir> = None;
+        let mut spilled_append_vecs = Vec::new();
         for entry in entries.by_ref() {
-            let entry = entry?;
+            let mut entry = entry?;
             let path = entry.path()?;
             if Self::is_snapshot_manifest_file(&path) {
                 snapshot_file = Some(entry);
                 break;
             } else if Self::is_appendvec_file(&path) {
-                // TODO Support archives where AppendVecs precede snapshot manifests
-                return Err(SnapshotError::UnexpectedAppendVec);
+                // The manifest is needed to know the length of the AppendVec, keep it for later.
+                let name = path.file_name().expect("checked by is_appendvec_file");
+                let (slot, id) = parse_append_vec_name(name).expect("checked by is_appendvec_file");
+                let spill_dir = match &mut spill_dir {
+                    Some(spill_dir) => spill_dir,
+                    None => spill_dir.insert(tempfile::tempdir()?),
+                };
+                let spilled_path = spill_dir.path().join(name);
+                let mut spilled_file = BufWriter::new(File::create(&spilled_path)?);
+                std::io::copy(&mut entry, &mut spilled_file)?;
+                spilled_file.flush()?;
+                spilled_append_vecs.push((slot, id, spilled_path));
             }
         }
+        if !spilled_append_vecs.is_empty() {
+            info!(
+                "Spilled {} AppendVecs found before the snapshot manifest",
+                spilled_append_vecs.len()
+            );
+        }
         let snapshot_file = snapshot_file.ok_or(SnapshotError::NoSnapshotManifest)?;
         //let snapshot_file_len = snapshot_file.size();
         let snapshot_file_path = snapshot_file.path()?.as_ref().to_path_buf();
@@ -68,13 +204,14 @@ where
 
         let pre_unpack = Instant::now();
         let versioned_bank: DeserializableVersionedBank = deserialize_from(&mut snapshot_file)?;
-        drop(versioned_bank);
         let versioned_bank_post_time = Instant::now();
 
         let accounts_db_fields: AccountsDbFields<SerializableAccountStorageEntry> =
             deserialize_from(&mut snapshot_file)?;
         let accounts_db_fields_post_time = Instant::now();
         drop(snapshot_file);
+        let manifest = SnapshotManifest::new(&versioned_bank, &accounts_db_fields);
+        drop(versioned_bank);
 
         info!(
             "Read bank fields in {:?}",
@@ -88,16 +225,38 @@ where
         Ok(ArchiveSnapshotExtractor {
             _archive: archive,
             accounts_db_fields,
+            manifest,
+            archive_format,
             entries: Some(entries),
+            spilled_append_vecs,
+            _spill_dir: spill_dir,
         })
     }
 
+    pub const fn archive_format(&self) -> ArchiveFormat {
+        self.archive_format
+    }
+
+    /// Directory the AppendVecs found before the manifest are spilled to.
+    #[cfg(test)]
+    fn spill_dir(&self) -> Option<&Path> {
+        self._spill_dir.as_ref().map(TempDir::path)
+    }
+
     fn unboxed_iter(&mut self) -> impl Iterator<Item = SnapshotResult<AppendVec>> + '_ {
-        self.entries
-            .take()
+        let spilled_append_vecs = std::mem::take(&mut self.spilled_append_vecs);
+        let entries = self.entries.take();
+        let this = &*self;
+        spilled_append_vecs
             .into_iter()
-            .flatten()
-            .filter_map(|entry| {
+            .map(|(slot, id, path)| {
+                let current_len = this.known_append_vec_len(slot, id)?;
+                let append_vec = AppendVec::new_from_file(&path, current_len, slot, id)?;
+                // the mapping keeps the data, the disk space is freed once it is dropped
+                std::fs::remove_file(path)?;
+                Ok(append_vec)
+            })
+            .chain(entries.into_iter().flatten().filter_map(|entry| {
                 let mut entry = match entry {
                     Ok(x) => x,
                     Err(e) => return Some(Err(e.into())),
@@ -107,16 +266,21 @@ where
                     Err(e) => return Some(Err(e.into())),
                 };
                 let (slot, id) = path.file_name().and_then(parse_append_vec_name)?;
-                Some(self.process_entry(&mut entry, slot, id))
-            })
+                Some(this.process_entry(&mut entry, slot, id))
+            }))
     }
 
     fn process_entry(
         &self,
-        entry: &mut Entry<'static, zstd::Decoder<'static, BufReader<Source>>>,
+        entry: &mut Entry<'static, ArchiveDecoder<Source>>,
         slot: u64,
         id: u64,
     ) -> SnapshotResult<AppendVec> {
+        let current_len = self.known_append_vec_len(slot, id)?;
+        Ok(AppendVec::new_from_reader(entry, current_len, slot, id)?)
+    }
+
+    fn known_append_vec_len(&self, slot: u64, id: u64) -> SnapshotResult<usize> {
         let known_vecs = self
             .accounts_db_fields
             .0
@@ -124,15 +288,10 @@ where
             .map(|v| &v[..])
             .unwrap_or(&[]);
         let known_vec = known_vecs.iter().find(|entry| entry.id == (id as usize));
-        let known_vec = match known_vec {
-            None => return Err(SnapshotError::UnexpectedAppendVec),
-            Some(v) => v,
-        };
-        Ok(AppendVec::new_from_reader(
-            entry,
-            known_vec.accounts_current_len,
-            slot,
-        )?)
+        match known_vec {
+            None => Err(SnapshotError::UnexpectedAppendVec),
+            Some(v) => Ok(v.accounts_current_len),
+        }
     }
 
     fn is_snapshot_manifest_file(path: &Path) -> bool {
@@ -172,8 +331,130 @@ where
     }
 }
 
-impl ArchiveSnapshotExtractor<File> {
-    pub fn open(path: &Path) -> SnapshotResult<Self> {
-        Self::from_reader(File::open(path)?)
+/// Extractor of an archive file opened with `ArchiveSnapshotExtractor::open`.
+pub type ArchiveFileSnapshotExtractor = ArchiveSnapshotExtractor<Box<dyn Read>>;
+
+impl ArchiveFileSnapshotExtractor {
+    /// Opens an archive file, detecting its format from the file extension and falling back
+    /// to the magic bytes. Progress is tracked on the compressed file.
+    pub fn open(path: &Path, progress_tracking: &dyn ReadProgressTracking) -> SnapshotResult<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let file = progress_tracking.new_read_progress_tracker(path, Box::new(file), file_len)?;
+        match archive_format_from_path(path) {
+            Some(archive_format) => Self::from_reader_with_format(file, archive_format),
+            None => Self::from_reader(file),
+        }
+    }
+}
+
+#[test]
+fn test_archive_format_detection() {
+    for archive_format in [
+        ArchiveFormat::TarZstd,
+        ArchiveFormat::TarGzip,
+        ArchiveFormat::TarBzip2,
+        ArchiveFormat::TarLz4,
+        ArchiveFormat::Tar,
+    ] {
+        let path = PathBuf::from(format!(
+            "snapshot-100-AvFf9oS8A8U78HdjT9YG2sTTThLHJZmhaMn2g8vkWYnr.{}",
+            archive_format.extension()
+        ));
+        assert_eq!(archive_format_from_path(&path), Some(archive_format));
+    }
+    assert_eq!(archive_format_from_path(Path::new("snapshot.zip")), None);
+
+    let tar = b"accounts/100.1".to_vec();
+    let zstd = zstd::encode_all(&tar[..], 0).unwrap();
+    assert_eq!(
+        archive_format_from_magic_bytes(&zstd),
+        ArchiveFormat::TarZstd
+    );
+    let mut lz4 = lz4::EncoderBuilder::new().build(Vec::new()).unwrap();
+    lz4.write_all(&tar).unwrap();
+    let (lz4, _) = lz4.finish();
+    assert_eq!(archive_format_from_magic_bytes(&lz4), ArchiveFormat::TarLz4);
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&tar).unwrap();
+    let gzip = gzip.finish().unwrap();
+    assert_eq!(
+        archive_format_from_magic_bytes(&gzip),
+        ArchiveFormat::TarGzip
+    );
+    let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    bzip2.write_all(&tar).unwrap();
+    let bzip2 = bzip2.finish().unwrap();
+    assert_eq!(
+        archive_format_from_magic_bytes(&bzip2),
+        ArchiveFormat::TarBzip2
+    );
+    assert_eq!(archive_format_from_magic_bytes(&tar), ArchiveFormat::Tar);
+}
+
+#[test]
+fn test_append_vecs_before_manifest() {
+    use {
+        crate::{
+            append_vec::test_append_vec_bytes, append_vec_iter, solana::test_snapshot_manifest,
+        },
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    let owner = Pubkey::new_unique();
+    let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+    let first = test_append_vec_bytes(&[(a, 1, 1, owner, b"a")]);
+    let second = test_append_vec_bytes(&[(b, 2, 1, owner, b"b")]);
+    let third = test_append_vec_bytes(&[(c, 3, 1, owner, b"c")]);
+    let manifest = test_snapshot_manifest(
+        10,
+        &[
+            (5, 1, first.len()),
+            (5, 2, second.len()),
+            (6, 3, third.len()),
+        ],
+    );
+    let mut tar = tar::Builder::new(Vec::new());
+    for (path, data) in [
+        ("accounts/5.1", &first),
+        ("accounts/5.2", &second),
+        ("snapshots/10/10", &manifest),
+        ("accounts/6.3", &third),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, path, data.as_slice()).unwrap();
+    }
+    let tar = tar.into_inner().unwrap();
+
+    let mut extractor = ArchiveSnapshotExtractor::from_reader(std::io::Cursor::new(tar)).unwrap();
+    assert_eq!(extractor.slot(), 10);
+    assert_eq!(extractor.manifest().slot, 10);
+    let spill_dir = extractor.spill_dir().unwrap().to_path_buf();
+    let spilled_files = || spill_dir.read_dir().unwrap().count();
+    assert_eq!(spilled_files(), 2);
+    let mut accounts = Vec::new();
+    for (i, append_vec) in extractor.iter().enumerate() {
+        let append_vec = append_vec.unwrap();
+        // spilled files are deleted as they are yielded
+        assert_eq!(spilled_files(), 1usize.saturating_sub(i));
+        for handle in append_vec_iter(&append_vec) {
+            let stored = handle.access().unwrap();
+            accounts.push((
+                append_vec.slot(),
+                append_vec.id(),
+                stored.meta.pubkey,
+                stored.data.to_vec(),
+            ));
+        }
     }
+    assert_eq!(
+        accounts,
+        vec![
+            (5, 1, a, b"a".to_vec()),
+            (5, 2, b, b"b".to_vec()),
+            (6, 3, c, b"c".to_vec()),
+        ]
+    );
 }
@@ -1,17 +1,32 @@
 use clap::Parser;
 use itertools::Itertools;
 use solana_sdk::pubkey::Pubkey;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use {
     log::info,
     solana_accountsdb_compression_dictionary_utils::{
-        append_vec::AppendVec, append_vec_iter, archived::ArchiveSnapshotExtractor,
-        parallel::AppendVecConsumer, partial_pubkey_by_bits::PartialPubkeyByBits,
+        append_vec::AppendVec,
+        append_vec_iter,
+        codec::CodecKind,
+        dedup::{LatestAccountsExtractor, LatestAccountsIndex},
+        dictionary::{DictionaryBundle, DictionarySource, TrainingStats},
+        dictionary_builder::{DictionaryBuilder, ZstdTrainingParams},
+        dictionary_key::{
+            AccountKeyScheme, DictionaryKey, KeyScheme, OwnerKeyScheme, OwnerRanking,
+        },
+        incremental::SnapshotArchivesExtractor,
+        parallel::{
+            par_fold_append_vecs, par_map_within_budget, AppendVecConsumer, AppendVecFolder,
+        },
+        partial_pubkey_by_bits::MAX_NB_BITS,
+        progress::LoggingReadProgressTracking,
+        sampling::{account_id, ReservoirSampler, SampleWeighting, Samples},
+        size_selection::{select_sizes, KeyCandidates, MIN_TRAINING_SAMPLES},
+        split::{HoldoutSplit, Partition},
         SnapshotExtractor,
     },
-    std::fs::File,
 };
 
 #[derive(Parser, Debug)]
@@ -20,45 +35,124 @@ pub struct Args {
     #[arg(short = 'a', long)]
     pub snapshot_archive_path: String,
 
+    /// incremental snapshot based on the full snapshot archive
+    #[arg(short = 'i', long)]
+    pub incremental_snapshot_archive_path: Option<String>,
+
+    /// only use the newest version of every account, reads the snapshot twice
+    #[arg(long)]
+    pub latest_accounts_only: bool,
+
+    /// memory used to deduplicate accounts before spilling to disk
+    #[arg(long, default_value_t = 1024 * 1024 * 1024)] // 1gb
+    pub dedup_memory_budget: usize,
+
+    /// maximum number of sampled accounts per key
     #[arg(short = 's', long, default_value_t = 10_000_000)]
     pub sample_size: usize,
 
     #[arg(short = 'd', long, default_value_t = 1024)]
     pub dictionary_size_per_program: usize,
 
+    /// pick the size of every dictionary among --dictionary-sizes so that all dictionaries fit
+    /// in this many bytes, overrides -d
+    #[arg(long)]
+    pub dictionary_budget: Option<usize>,
+
+    /// candidate dictionary sizes when selecting sizes
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "1024,4096,16384,65536,131072"
+    )]
+    pub dictionary_sizes: Vec<usize>,
+
+    /// when selecting sizes, every n-th sample is held out to measure the compressed size, at
+    /// least 2
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(2..)
+    )]
+    pub validation_every: usize,
+
+    /// level used to measure the compressed size when selecting sizes, defaults to the level of
+    /// the codec
+    #[arg(long)]
+    pub level: Option<i32>,
+
+    /// codec the dictionaries are built for, one of lz4-flex or zstd
+    #[arg(short = 'c', long, default_value_t = CodecKind::Zstd)]
+    pub codec: CodecKind,
+
+    /// one of zstd, zstd-content, segments or segments-zstd, defaults to zstd for zstd and
+    /// zstd-content for lz4
+    #[arg(long)]
+    pub dictionary_builder: Option<DictionaryBuilder>,
+
+    /// zstd trainer, <cover|fastcover>[:k=,d=,f=,steps=,split=,accel=,level=,dict-id=,threads=,optimize]
+    /// with the zstd cli defaults, k and d are optimized unless both are given and split only
+    /// applies when optimizing, also used to finalize segments-zstd dictionaries
+    #[arg(long, default_value_t = ZstdTrainingParams::default())]
+    pub zstd_training: ZstdTrainingParams,
+
+    /// maximum bytes sampled per key
     #[arg(short = 'm', long, default_value_t = 1024 * 1024 * 1024)] // 1gb
     pub max_sample_vector_length: usize,
 
+    /// maximum bytes sampled for all keys combined, split evenly between the threads while
+    /// sampling, the samples depend on the number of threads through the share of each thread
+    #[arg(long, default_value_t = 4 * 1024 * 1024 * 1024)] // 4gb
+    pub sampling_memory_budget: usize,
+
+    /// maximum bytes of samples of the dictionaries trained at the same time, the samples are
+    /// already in memory and the zstd trainers allocate several times more, about 8 bytes per
+    /// sample byte for cover
+    #[arg(long, default_value_t = 4 * 1024 * 1024 * 1024)] // 4gb
+    pub training_memory_budget: usize,
+
+    /// number of threads sampling accounts and training dictionaries, defaults to the number of
+    /// cpus
+    #[arg(short = 't', long)]
+    pub threads: Option<usize>,
+
+    /// seed of the sampling, runs with the same seed and snapshot sample the same accounts
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// probability of an account to be sampled, one of uniform or data-len
+    #[arg(long, default_value_t = SampleWeighting::Uniform)]
+    pub sample_weighting: SampleWeighting,
+
     #[arg(short = 'o', long, default_value_t = String::from("dictionary.bin"))]
     pub out_dictionary: String,
 
+    /// owners sharing their first n bits use the same dictionary, at most 256
     #[arg(short = 'n', long, default_value_t = 8)]
-    pub number_of_bits_of_pubkey: u8,
-}
+    pub number_of_bits_of_pubkey: u16,
 
-struct Samples {
-    pub samples: Vec<u8>,
-    pub sizes: Vec<usize>,
-    pub total_size: usize,
-}
+    /// key dictionaries on the full owner of the top k owners, other accounts share a fallback
+    /// dictionary, overrides -n
+    #[arg(long, conflicts_with = "hierarchical")]
+    pub top_owners: Option<u32>,
 
-impl Samples {
-    pub fn new(data: &[u8]) -> Self {
-        Self {
-            samples: data.to_vec(),
-            sizes: vec![data.len()],
-            total_size: data.len(),
-        }
-    }
+    /// rank owners by number of accounts or data bytes, one of accounts or bytes
+    #[arg(long, default_value_t = OwnerRanking::Accounts)]
+    pub rank_owners_by: OwnerRanking,
 
-    pub fn add(&mut self, data: &[u8]) {
-        self.sizes.push(data.len());
-        self.total_size += data.len();
-        self.samples.extend_from_slice(data);
-    }
-}
+    /// split the accounts of an owner, one of none, discriminator, data-len or data-len-bucket
+    #[arg(long, default_value_t = AccountKeyScheme::None)]
+    pub account_key: AccountKeyScheme,
 
-type DictionaryMap = HashMap<PartialPubkeyByBits, Vec<u8>>;
+    /// train dictionaries for owner and account key, owner, first n bits of the owner and all
+    /// accounts, the most specific one available is used
+    #[arg(long)]
+    pub hierarchical: bool,
+
+    /// hold out the accounts whose pubkey hash modulo n is zero, they are not used for training
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub holdout: Option<u32>,
+}
 
 pub fn main() -> anyhow::Result<()> {
     env_logger::init_from_env(
@@ -70,84 +164,241 @@ pub fn main() -> anyhow::Result<()> {
 
     let Args {
         snapshot_archive_path,
+        incremental_snapshot_archive_path,
+        latest_accounts_only,
+        dedup_memory_budget,
         sample_size,
         dictionary_size_per_program,
+        dictionary_budget,
+        dictionary_sizes,
+        validation_every,
+        level,
+        codec,
+        dictionary_builder,
+        zstd_training,
         out_dictionary,
         max_sample_vector_length,
+        sampling_memory_budget,
+        training_memory_budget,
+        threads,
+        seed,
+        sample_weighting,
         number_of_bits_of_pubkey,
+        top_owners,
+        rank_owners_by,
+        account_key,
+        hierarchical,
+        holdout,
     } = args;
+    let split = holdout.map(HoldoutSplit::new);
+    if !codec.supports_dictionary() {
+        anyhow::bail!("{codec} codec does not support dictionaries");
+    }
+    let dictionary_builder = dictionary_builder.unwrap_or(DictionaryBuilder::default_for(codec));
+    let threads = threads.unwrap_or_else(num_cpus::get).max(1);
 
     let archive_path = PathBuf::from_str(snapshot_archive_path.as_str()).unwrap();
 
-    let mut loader: ArchiveSnapshotExtractor<File> =
-        ArchiveSnapshotExtractor::open(&archive_path).unwrap();
-
-    let mut samples: HashMap<PartialPubkeyByBits, Samples> = HashMap::new();
+    let incremental_archive_path = incremental_snapshot_archive_path
+        .map(|incremental_archive_path| PathBuf::from_str(&incremental_archive_path).unwrap());
+    let progress_tracking = LoggingReadProgressTracking::default();
+    // the latest accounts index also drops the accounts overwritten by the incremental snapshot,
+    // which is then read once to build it and once to iterate
+    let open_loader = || {
+        SnapshotArchivesExtractor::open(
+            &archive_path,
+            incremental_archive_path.as_deref(),
+            false,
+            &progress_tracking,
+        )
+        .unwrap()
+    };
 
-    let mut counter = 0u64;
-    for vec in loader.iter() {
-        let append_vec = vec.unwrap();
-        // info!("size: {:?}", append_vec.len());
-        for handle in append_vec_iter(&append_vec) {
-            counter += 1;
-            let stored = handle.access().unwrap();
-            let data_len = stored.meta.data_len as usize;
-            if stored.account_meta.owner == Pubkey::default() || stored.meta.data_len < 8 {
-                continue;
-            }
+    let mut loader: SnapshotArchivesExtractor;
+    let mut latest_accounts_loader: LatestAccountsExtractor<SnapshotArchivesExtractor>;
+    let (manifest, append_vecs) = if latest_accounts_only {
+        let index = LatestAccountsIndex::build(&mut open_loader(), dedup_memory_budget).unwrap();
+        latest_accounts_loader = LatestAccountsExtractor::new(open_loader(), index);
+        (
+            latest_accounts_loader.manifest().clone(),
+            latest_accounts_loader.iter(),
+        )
+    } else {
+        loader = open_loader();
+        (loader.manifest().clone(), loader.iter())
+    };
+    log::info!("snapshot manifest: {manifest:?}");
+    let owner_prefix = OwnerKeyScheme::OwnerPrefixBits {
+        nb_bits: number_of_bits_of_pubkey,
+    };
+    let full_owner = OwnerKeyScheme::OwnerPrefixBits {
+        nb_bits: MAX_NB_BITS,
+    };
+    let key_schemes = if hierarchical {
+        [
+            KeyScheme::new(full_owner, account_key),
+            KeyScheme::new(full_owner, AccountKeyScheme::None),
+            KeyScheme::new(owner_prefix, AccountKeyScheme::None),
+            KeyScheme::GLOBAL,
+        ]
+        .into_iter()
+        .dedup()
+        .collect_vec()
+    } else if let Some(top_k) = top_owners {
+        let top_owners = OwnerKeyScheme::TopOwners {
+            top_k,
+            ranking: rank_owners_by,
+        };
+        vec![KeyScheme::new(top_owners, account_key), KeyScheme::GLOBAL]
+    } else {
+        vec![KeyScheme::new(owner_prefix, account_key)]
+    };
+    // the fallback dictionary of the top owners is trained on the samples of the other owners
+    let sampled_key_schemes = if top_owners.is_some() {
+        &key_schemes[..1]
+    } else {
+        &key_schemes[..]
+    };
 
-            let data = stored.data;
-            let key = PartialPubkeyByBits::new(stored.account_meta.owner, number_of_bits_of_pubkey);
-            match samples.entry(key) {
-                std::collections::hash_map::Entry::Occupied(mut occ) => {
-                    let val = occ.get_mut();
-                    if val.sizes.len() >= sample_size
-                        || val.samples.len() + data_len >= max_sample_vector_length
-                    {
-                        continue;
-                    }
-                    val.add(data);
-                }
-                std::collections::hash_map::Entry::Vacant(vac) => {
-                    vac.insert(Samples::new(data));
-                }
-            };
-        }
-    }
+    let SamplingFolder {
+        sampler,
+        accounts: counter,
+        ..
+    } = par_fold_append_vecs(
+        append_vecs,
+        || {
+            Ok(SamplingFolder {
+                key_schemes: sampled_key_schemes.to_vec(),
+                split,
+                sampler: ReservoirSampler::new(
+                    seed,
+                    sample_weighting,
+                    sample_size,
+                    max_sample_vector_length,
+                    sampling_memory_budget / threads,
+                ),
+                accounts: 0,
+            })
+        },
+        threads,
+    )?;
+    log::info!("sampled {} bytes", sampler.sample_bytes());
+    let mut samples = sampler.into_samples();
     println!("iterated over : {} accounts", counter);
     let all_program_ids = samples.iter().map(|x| *x.0).collect_vec();
 
-    let mut dictionaries = DictionaryMap::new();
-    for (key, ite_sample) in samples.drain() {
-        if ite_sample.sizes.len() < 32 {
-            continue;
+    if let OwnerKeyScheme::TopOwners { top_k, ranking } = key_schemes[0].owner {
+        let mut owner_totals = HashMap::new();
+        for (key, sample) in &samples {
+            let (accounts, bytes) = owner_totals.entry(key.owner).or_insert((0, 0));
+            *accounts += sample.accounts;
+            *bytes += sample.bytes;
+        }
+        let top_owners = ranking.top_owners(&owner_totals, top_k as usize);
+        let mut fallback = Samples::default();
+        for key in samples.keys().copied().sorted_unstable().collect_vec() {
+            if !top_owners.contains(&key.owner) {
+                let sample = samples.remove(&key).unwrap();
+                fallback.merge(sample, sample_size, max_sample_vector_length);
+            }
         }
+        println!(
+            "{} accounts of {} owners use the fallback dictionary",
+            fallback.accounts,
+            owner_totals.len() - top_owners.len()
+        );
+        samples.insert(DictionaryKey::ALL, fallback);
+    }
 
-        let dict = match zstd::dict::from_continuous(
-            &ite_sample.samples,
-            &ite_sample.sizes,
-            dictionary_size_per_program,
-        ) {
-            Ok(v) => v,
-            Err(e) => {
-                println!(
-                    "error {}, ite_sample: {}, number of samples: {}",
-                    e,
-                    ite_sample.samples.len(),
-                    ite_sample.sizes.len()
-                );
-                continue;
+    let mut dictionaries =
+        DictionaryBundle::new(codec, key_schemes, Some(DictionarySource::from(&manifest)));
+    dictionaries.split = split;
+    // sorted so that dictionary ids are reproducible
+    let samples = samples
+        .into_iter()
+        .filter(|(_, samples)| samples.sizes.len() >= MIN_TRAINING_SAMPLES)
+        .sorted_unstable_by_key(|(key, _)| *key)
+        .collect_vec();
+    let training_cost = |(_, samples): &(DictionaryKey, Samples)| samples.samples.len();
+    let training_error = |e: &dyn std::fmt::Display, samples: &Samples| {
+        println!(
+            "error {}, ite_sample: {}, number of samples: {}",
+            e,
+            samples.samples.len(),
+            samples.sizes.len()
+        )
+    };
+    if let Some(dictionary_budget) = dictionary_budget {
+        let trained = par_map_within_budget(
+            &samples,
+            threads,
+            training_memory_budget,
+            training_cost,
+            |(_, ite_sample)| {
+                KeyCandidates::train(
+                    ite_sample,
+                    &dictionary_sizes,
+                    dictionary_builder,
+                    &zstd_training,
+                    codec,
+                    level,
+                    validation_every,
+                )
+            },
+        );
+        let mut candidates = BTreeMap::new();
+        for ((key, ite_sample), key_candidates) in samples.iter().zip(trained) {
+            match key_candidates {
+                Ok(Some(key_candidates)) => {
+                    candidates.insert(*key, key_candidates);
+                }
+                Ok(None) => {}
+                Err(e) => training_error(&e, ite_sample),
             }
-        };
-        dictionaries.insert(key, dict);
+        }
+        let selected = select_sizes(&candidates, dictionary_budget);
+        for (key, index) in selected {
+            let key_candidates = candidates.get_mut(&key).unwrap();
+            let stats = key_candidates.stats(index);
+            let dict = std::mem::take(&mut key_candidates.candidates[index].dictionary);
+            dictionaries.insert(key, dict, stats);
+        }
+        println!(
+            "selected dictionaries of {}/{} bytes",
+            dictionaries.dictionaries_len(),
+            dictionary_budget
+        );
+    } else {
+        let trained = par_map_within_budget(
+            &samples,
+            threads,
+            training_memory_budget,
+            training_cost,
+            |(_, ite_sample)| {
+                dictionary_builder.build(ite_sample, dictionary_size_per_program, &zstd_training)
+            },
+        );
+        for ((key, ite_sample), dict) in samples.iter().zip(trained) {
+            let dict = match dict {
+                Ok(v) => v,
+                Err(e) => {
+                    training_error(&e, ite_sample);
+                    continue;
+                }
+            };
+            let stats = TrainingStats {
+                dictionary_size: dictionary_size_per_program as u64,
+                ..ite_sample.stats()
+            };
+            dictionaries.insert(*key, dict, stats);
+        }
     }
     println!(
         "program ids in dictionaries : {}/{}",
         dictionaries.len(),
         all_program_ids.len()
     );
-    let dictionary = bincode::serialize(&dictionaries).unwrap();
-    std::fs::write(out_dictionary, dictionary).unwrap();
+    dictionaries.save(Path::new(&out_dictionary))?;
 
     // println!("following programs are not included");
     // for program_id in all_program_ids {
@@ -160,6 +411,43 @@ pub fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Samples the accounts of the append vecs given to one thread.
+struct SamplingFolder {
+    key_schemes: Vec<KeyScheme>,
+    split: Option<HoldoutSplit>,
+    sampler: ReservoirSampler<DictionaryKey>,
+    /// Accounts iterated over.
+    accounts: u64,
+}
+
+impl AppendVecFolder for SamplingFolder {
+    fn fold(&mut self, append_vec: AppendVec) -> anyhow::Result<()> {
+        for handle in append_vec_iter(&append_vec) {
+            self.accounts += 1;
+            let stored = handle.access().unwrap();
+            if stored.account_meta.owner == Pubkey::default() || stored.meta.data_len < 8 {
+                continue;
+            }
+            if !Partition::Train.contains(self.split.as_ref(), &stored.meta.pubkey) {
+                continue;
+            }
+
+            let draw = self
+                .sampler
+                .draw(&account_id(&stored.meta.pubkey, append_vec.slot()));
+            for key_scheme in &self.key_schemes {
+                self.sampler.add(key_scheme.key(&stored), draw, stored.data);
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.sampler.merge(other.sampler);
+        self.accounts += other.accounts;
+    }
+}
+
 struct SimpleLogConsumer {}
 
 #[async_trait::async_trait]
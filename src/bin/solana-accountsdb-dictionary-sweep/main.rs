@@ -0,0 +1,368 @@
+use std::{fs::File, io::BufWriter, path::PathBuf, str::FromStr};
+
+use clap::Parser;
+use solana_accountsdb_compression_dictionary_utils::{
+    append_vec_iter,
+    codec::{AccountCodec, CodecKind},
+    dedup::{LatestAccountsExtractor, LatestAccountsIndex},
+    dictionary::{DictionaryBundle, DictionaryResolver},
+    dictionary_builder::{DictionaryBuilder, ZstdTrainingParams},
+    dictionary_key::{AccountKeyScheme, KeyScheme, OwnerKeyScheme},
+    incremental::SnapshotArchivesExtractor,
+    progress::LoggingReadProgressTracking,
+    report::ReportFormat,
+    sampling::{account_id, ReservoirSampler, SampleWeighting},
+    split::{HoldoutSplit, Partition},
+    sweep::{evaluate, train_dictionaries, EvaluationAccounts, SweepResult},
+    SnapshotExtractor,
+};
+use solana_sdk::pubkey::Pubkey;
+
+#[global_allocator]
+static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+/// Reads the snapshot once, then trains and evaluates dictionaries for every combination of
+/// owner prefix bits, dictionary sizes, codecs and levels.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    #[arg(short = 'a', long)]
+    pub snapshot_archive_path: String,
+
+    /// incremental snapshot based on the full snapshot archive
+    #[arg(short = 'i', long)]
+    pub incremental_snapshot_archive_path: Option<String>,
+
+    /// only use the newest version of every account, reads the snapshot twice
+    #[arg(long)]
+    pub latest_accounts_only: bool,
+
+    /// memory used to deduplicate accounts before spilling to disk
+    #[arg(long, default_value_t = 1024 * 1024 * 1024)] // 1gb
+    pub dedup_memory_budget: usize,
+
+    /// owners sharing their first n bits use the same dictionary, at most 256
+    #[arg(
+        short = 'n',
+        long,
+        value_delimiter = ',',
+        default_value = "0,4,8,16,256"
+    )]
+    pub number_of_bits_of_pubkey: Vec<u16>,
+
+    #[arg(
+        short = 'd',
+        long,
+        value_delimiter = ',',
+        default_value = "512,1024,2048,4096,8192,16384,32768,65536,131072"
+    )]
+    pub dictionary_sizes: Vec<usize>,
+
+    /// codecs to evaluate, among none, lz4, lz4-flex and zstd
+    #[arg(
+        short = 'c',
+        long,
+        value_delimiter = ',',
+        default_value = "lz4-flex,zstd"
+    )]
+    pub codecs: Vec<CodecKind>,
+
+    /// acceleration of lz4 and compression level of zstd
+    #[arg(short = 'l', long, value_delimiter = ',', default_value = "1,3")]
+    pub levels: Vec<i32>,
+
+    /// how dictionaries are built, among zstd, zstd-content, segments and segments-zstd, defaults
+    /// to zstd for zstd and zstd-content for lz4
+    #[arg(long, value_delimiter = ',')]
+    pub dictionary_builders: Vec<DictionaryBuilder>,
+
+    /// zstd trainer, <cover|fastcover>[:k=,d=,f=,steps=,split=,accel=,level=,dict-id=,threads=,optimize]
+    /// with the zstd cli defaults, k and d are optimized unless both are given and split only
+    /// applies when optimizing, also used to finalize segments-zstd dictionaries
+    #[arg(long, default_value_t = ZstdTrainingParams::default())]
+    pub zstd_training: ZstdTrainingParams,
+
+    /// split the accounts of an owner, one of none, discriminator, data-len or data-len-bucket
+    #[arg(long, default_value_t = AccountKeyScheme::None)]
+    pub account_key: AccountKeyScheme,
+
+    /// maximum number of sampled accounts per key
+    #[arg(short = 's', long, default_value_t = 10_000_000)]
+    pub sample_size: usize,
+
+    /// maximum bytes sampled per key
+    #[arg(short = 'm', long, default_value_t = 1024 * 1024 * 1024)] // 1gb
+    pub max_sample_vector_length: usize,
+
+    /// maximum bytes sampled for all keys combined while the snapshot is read, split evenly
+    /// between the numbers of bits
+    #[arg(long, default_value_t = 4 * 1024 * 1024 * 1024)] // 4gb
+    pub sampling_memory_budget: usize,
+
+    /// maximum bytes of the accounts kept to evaluate the dictionaries
+    #[arg(long, default_value_t = 1024 * 1024 * 1024)] // 1gb
+    pub evaluation_memory_budget: usize,
+
+    /// seed of the sampling, runs with the same seed and snapshot sample the same accounts
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// probability of an account to be sampled, one of uniform or data-len
+    #[arg(long, default_value_t = SampleWeighting::Uniform)]
+    pub sample_weighting: SampleWeighting,
+
+    /// accounts whose pubkey hash modulo n is zero are evaluated, the others are used for training
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(2..))]
+    pub holdout: u32,
+
+    #[arg(short = 'o', long, default_value_t = String::from("sweep.csv"))]
+    pub out: String,
+
+    /// one of csv or json, defaults to the extension of the output
+    #[arg(long)]
+    pub format: Option<ReportFormat>,
+}
+
+pub fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+    );
+
+    let args = Args::parse();
+    println!("sweep args : {:?}", args);
+
+    let Args {
+        snapshot_archive_path,
+        incremental_snapshot_archive_path,
+        latest_accounts_only,
+        dedup_memory_budget,
+        number_of_bits_of_pubkey,
+        dictionary_sizes,
+        codecs,
+        levels,
+        dictionary_builders,
+        zstd_training,
+        account_key,
+        sample_size,
+        max_sample_vector_length,
+        sampling_memory_budget,
+        evaluation_memory_budget,
+        seed,
+        sample_weighting,
+        holdout,
+        out,
+        format,
+    } = args;
+    let split = HoldoutSplit::new(holdout);
+    let format = format.unwrap_or(ReportFormat::from_path(&out));
+
+    let archive_path = PathBuf::from_str(snapshot_archive_path.as_str()).unwrap();
+
+    let incremental_archive_path = incremental_snapshot_archive_path
+        .map(|incremental_archive_path| PathBuf::from_str(&incremental_archive_path).unwrap());
+    let progress_tracking = LoggingReadProgressTracking::default();
+    // the latest accounts index also drops the accounts overwritten by the incremental snapshot,
+    // which is then read once to build it and once to iterate
+    let open_loader = || {
+        SnapshotArchivesExtractor::open(
+            &archive_path,
+            incremental_archive_path.as_deref(),
+            false,
+            &progress_tracking,
+        )
+        .unwrap()
+    };
+
+    let mut loader: SnapshotArchivesExtractor;
+    let mut latest_accounts_loader: LatestAccountsExtractor<SnapshotArchivesExtractor>;
+    let (manifest, append_vecs) = if latest_accounts_only {
+        let index = LatestAccountsIndex::build(&mut open_loader(), dedup_memory_budget).unwrap();
+        latest_accounts_loader = LatestAccountsExtractor::new(open_loader(), index);
+        (
+            latest_accounts_loader.manifest().clone(),
+            latest_accounts_loader.iter(),
+        )
+    } else {
+        loader = open_loader();
+        (loader.manifest().clone(), loader.iter())
+    };
+    log::info!("snapshot manifest: {manifest:?}");
+
+    let key_schemes = number_of_bits_of_pubkey
+        .iter()
+        .map(|&nb_bits| KeyScheme::new(OwnerKeyScheme::OwnerPrefixBits { nb_bits }, account_key))
+        .collect::<Vec<_>>();
+    let mut samplers = key_schemes
+        .iter()
+        .map(|_| {
+            ReservoirSampler::new(
+                seed,
+                sample_weighting,
+                sample_size,
+                max_sample_vector_length,
+                sampling_memory_budget / key_schemes.len().max(1),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut evaluation_sampler = ReservoirSampler::new(
+        seed,
+        SampleWeighting::Uniform,
+        usize::MAX,
+        evaluation_memory_budget,
+        evaluation_memory_budget,
+    );
+
+    let mut counter = 0u64;
+    for vec in append_vecs {
+        let append_vec = vec.unwrap();
+        for handle in append_vec_iter(&append_vec) {
+            counter += 1;
+            let stored = handle.access().unwrap();
+            let draw = evaluation_sampler.draw(&account_id(&stored.meta.pubkey, append_vec.slot()));
+            if Partition::Test.contains(Some(&split), &stored.meta.pubkey) {
+                if stored.meta.data_len >= 64 {
+                    let sample =
+                        EvaluationAccounts::sample(&stored.account_meta.owner, stored.data);
+                    evaluation_sampler.add((), draw, &sample);
+                }
+                continue;
+            }
+            if stored.account_meta.owner == Pubkey::default() || stored.meta.data_len < 8 {
+                continue;
+            }
+            for (key_scheme, sampler) in key_schemes.iter().zip(&mut samplers) {
+                sampler.add(key_scheme.key(&stored), draw, stored.data);
+            }
+        }
+    }
+    println!("iterated over : {} accounts", counter);
+    let accounts = EvaluationAccounts::from_samples(
+        evaluation_sampler
+            .into_samples()
+            .remove(&())
+            .unwrap_or_default(),
+    );
+    println!(
+        "evaluating {} accounts of {} bytes",
+        accounts.len(),
+        accounts.data_len()
+    );
+
+    let codec_levels = |codec: CodecKind| {
+        if codec.has_level() {
+            levels.iter().copied().map(Some).collect()
+        } else {
+            vec![None]
+        }
+    };
+
+    let codec_builders = |codec: CodecKind| {
+        if dictionary_builders.is_empty() {
+            vec![DictionaryBuilder::default_for(codec)]
+        } else {
+            dictionary_builders.clone()
+        }
+    };
+    let codecs_with_dictionary = codecs
+        .iter()
+        .copied()
+        .filter(|codec| codec.supports_dictionary())
+        .collect::<Vec<_>>();
+    let mut builders = codecs_with_dictionary
+        .iter()
+        .flat_map(|codec| codec_builders(*codec))
+        .collect::<Vec<_>>();
+    builders.sort_unstable_by_key(|builder| builder.name());
+    builders.dedup();
+
+    let mut results = Vec::new();
+    // baseline without dictionaries
+    for &codec in &codecs {
+        let no_dictionaries = DictionaryBundle::new(codec, vec![KeyScheme::GLOBAL], None);
+        for level in codec_levels(codec) {
+            let mut dictionary_codecs = DictionaryResolver::new(vec![KeyScheme::GLOBAL]);
+            let mut default_codec = codec.create(level, None)?;
+            let stats = evaluate(&mut dictionary_codecs, &mut default_codec, &accounts);
+            results.push(SweepResult::new(
+                None,
+                None,
+                None,
+                level,
+                &no_dictionaries,
+                &stats,
+            ));
+        }
+    }
+
+    for (key_scheme, sampler) in key_schemes.iter().zip(samplers) {
+        let OwnerKeyScheme::OwnerPrefixBits { nb_bits } = key_scheme.owner else {
+            unreachable!()
+        };
+        log::info!(
+            "sampled {} bytes for {nb_bits} bits",
+            sampler.sample_bytes()
+        );
+        let samples = sampler.into_samples();
+        for &dictionary_size in &dictionary_sizes {
+            // dictionaries are built once and shared by the codecs using the same builder, the
+            // bundle is labelled with the codec it is evaluated with
+            let mut dictionaries = builders
+                .iter()
+                .map(|&builder| {
+                    let codec = codecs_with_dictionary
+                        .iter()
+                        .copied()
+                        .find(|codec| codec_builders(*codec).contains(&builder))
+                        .expect("builders are those of the codecs with dictionary");
+                    let dictionaries = train_dictionaries(
+                        &samples,
+                        *key_scheme,
+                        codec,
+                        builder,
+                        &zstd_training,
+                        dictionary_size,
+                    );
+                    log::info!(
+                        "built {} {builder} dictionaries of {dictionary_size} bytes for {nb_bits} bits",
+                        dictionaries.len()
+                    );
+                    (builder, dictionaries)
+                })
+                .collect::<Vec<_>>();
+            for &codec in &codecs_with_dictionary {
+                for (builder, dictionaries) in &mut dictionaries {
+                    if !codec_builders(codec).contains(builder) {
+                        continue;
+                    }
+                    dictionaries.codec = codec;
+                    for level in codec_levels(codec) {
+                        let mut dictionary_codecs: DictionaryResolver<Box<dyn AccountCodec>> =
+                            DictionaryResolver::from_bundle(dictionaries, |entry| {
+                                codec.create(level, Some(&entry.dictionary))
+                            })?;
+                        let mut default_codec = codec.create(level, None)?;
+                        let stats = evaluate(&mut dictionary_codecs, &mut default_codec, &accounts);
+                        let result = SweepResult::new(
+                            Some(nb_bits),
+                            Some(dictionary_size),
+                            Some(*builder),
+                            level,
+                            dictionaries,
+                            &stats,
+                        );
+                        println!(
+                            "{nb_bits} bits, {dictionary_size} bytes {builder}, {codec} {level:?}: {:.4} compression ratio",
+                            result.compression_ratio
+                        );
+                        results.push(result);
+                    }
+                }
+            }
+        }
+    }
+
+    format.write(BufWriter::new(File::create(&out)?), &results)?;
+    println!("wrote {} results to {out}", results.len());
+
+    Ok(())
+}
@@ -1,17 +1,26 @@
 use std::{
-    collections::HashMap,
     fs::File,
-    path::PathBuf,
+    io::BufWriter,
+    path::{Path, PathBuf},
     str::FromStr,
-    time::{Duration, Instant},
 };
 
 use clap::Parser;
-use itertools::Itertools;
-use lz4::block::CompressionMode;
+use serde::Serialize;
 use solana_accountsdb_compression_dictionary_utils::{
-    append_vec_iter, archived::ArchiveSnapshotExtractor,
-    partial_pubkey_by_bits::PartialPubkeyByBits, SnapshotExtractor,
+    append_vec_iter,
+    codec::CodecKind,
+    compression_stats::LatencySummary,
+    dedup::{LatestAccountsExtractor, LatestAccountsIndex},
+    dictionary::DictionaryBundle,
+    dictionary_key::KeyScheme,
+    incremental::SnapshotArchivesExtractor,
+    parallel::par_fold_append_vecs,
+    progress::LoggingReadProgressTracking,
+    report::{CsvRecord, ReportFormat},
+    split::Partition,
+    tester::{is_evaluated, TesterFolder, TesterStats},
+    AppendVecIterator, SnapshotError, SnapshotExtractor,
 };
 
 #[global_allocator]
@@ -23,17 +32,207 @@ pub struct Args {
     #[arg(short = 'a', long)]
     pub snapshot_archive_path: String,
 
+    /// incremental snapshot based on the full snapshot archive
+    #[arg(short = 'i', long)]
+    pub incremental_snapshot_archive_path: Option<String>,
+
+    /// only use the newest version of every account, reads the snapshot twice
+    #[arg(long)]
+    pub latest_accounts_only: bool,
+
+    /// memory used to deduplicate accounts before spilling to disk
+    #[arg(long, default_value_t = 1024 * 1024 * 1024)] // 1gb
+    pub dedup_memory_budget: usize,
+
     #[arg(short = 'd', long)]
     pub dictionary: Option<String>,
 
+    /// one of none, lz4, lz4-flex or zstd, defaults to lz4 when an lz4 acceleration is given
+    /// and to lz4-flex otherwise
+    #[arg(short = 'c', long)]
+    pub codec: Option<CodecKind>,
+
+    /// acceleration of the lz4 codec, selects it when no codec is given
     #[arg(short = 's', long)]
     pub lz4_compression: Option<i32>,
 
+    /// compression level of the zstd codec
+    #[arg(short = 'l', long, default_value_t = zstd::DEFAULT_COMPRESSION_LEVEL)]
+    pub zstd_level: i32,
+
     #[arg(short = 'm', long)]
     pub max_number_of_accounts: Option<u64>,
+
+    /// accounts to evaluate, one of train, test or all, defaults to test when the dictionaries
+    /// were trained with a holdout
+    #[arg(long)]
+    pub partition: Option<Partition>,
+
+    /// also write the results to this file
+    #[arg(long)]
+    pub report_path: Option<String>,
+
+    /// format of the report, one of csv or json, defaults to the extension of the report path
+    #[arg(long, requires = "report_path")]
+    pub report_format: Option<ReportFormat>,
+
+    /// compare every account with and without dictionary, per dictionary key and for the n
+    /// owners with the most bytes
+    #[arg(long)]
+    pub breakdown_top_owners: Option<usize>,
+
+    /// file the breakdown is written to, printed when not given
+    #[arg(long, requires = "breakdown_top_owners")]
+    pub breakdown_path: Option<String>,
+
+    /// format of the breakdown, one of csv or json, defaults to the extension of the breakdown
+    /// path
+    #[arg(long, requires = "breakdown_path")]
+    pub breakdown_format: Option<ReportFormat>,
+
+    /// number of threads compressing accounts, defaults to the number of cpus
+    #[arg(short = 't', long)]
+    pub threads: Option<usize>,
+}
+
+/// Parameters and results of a tester run.
+#[derive(Debug, Serialize)]
+struct TesterReport {
+    snapshot_archive_path: String,
+    incremental_snapshot_archive_path: Option<String>,
+    latest_accounts_only: bool,
+    dictionary_path: Option<String>,
+    /// Hex sha256 of the dictionary file.
+    dictionary_checksum: Option<String>,
+    dictionary_slot: Option<u64>,
+    snapshot_slot: u64,
+    codec: CodecKind,
+    level: Option<i32>,
+    partition: String,
+    max_number_of_accounts: Option<u64>,
+    accounts: u64,
+    accounts_with_dict: usize,
+    accounts_without_dict: usize,
+    /// Accounts resolved with each key scheme of the dictionaries, most specific first.
+    accounts_per_level: Vec<usize>,
+    compression_errors: usize,
+    decompression_errors: usize,
+    total_size_uncompressed: u64,
+    total_size_compressed: u64,
+    compression_ratio: f64,
+    compression_ms: f64,
+    decompression_ms: f64,
+    /// Uncompressed GB per second, `None` when no time was measured.
+    compression_throughput: Option<f64>,
+    decompression_throughput: Option<f64>,
+    compression_latency_us: LatencyReport,
+    decompression_latency_us: LatencyReport,
 }
 
-type DictionaryMap = HashMap<PartialPubkeyByBits, Vec<u8>>;
+/// Latency percentiles of single accounts in microseconds.
+#[derive(Debug, Serialize)]
+struct LatencyReport {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl LatencyReport {
+    fn new(summary: &LatencySummary) -> Self {
+        let micros = |duration: std::time::Duration| duration.as_secs_f64() * 1e6;
+        Self {
+            p50: micros(summary.p50),
+            p90: micros(summary.p90),
+            p99: micros(summary.p99),
+            max: micros(summary.max),
+        }
+    }
+
+    fn record(&self) -> [String; 4] {
+        [self.p50, self.p90, self.p99, self.max].map(|micros| format!("{micros:.3}"))
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+}
+
+impl CsvRecord for TesterReport {
+    const HEADER: &'static [&'static str] = &[
+        "snapshot_archive_path",
+        "incremental_snapshot_archive_path",
+        "latest_accounts_only",
+        "dictionary_path",
+        "dictionary_checksum",
+        "dictionary_slot",
+        "snapshot_slot",
+        "codec",
+        "level",
+        "partition",
+        "max_number_of_accounts",
+        "accounts",
+        "accounts_with_dict",
+        "accounts_without_dict",
+        "accounts_per_level",
+        "compression_errors",
+        "decompression_errors",
+        "total_size_uncompressed",
+        "total_size_compressed",
+        "compression_ratio",
+        "compression_ms",
+        "decompression_ms",
+        "compression_throughput",
+        "decompression_throughput",
+        "compression_p50_us",
+        "compression_p90_us",
+        "compression_p99_us",
+        "compression_max_us",
+        "decompression_p50_us",
+        "decompression_p90_us",
+        "decompression_p99_us",
+        "decompression_max_us",
+    ];
+
+    fn record(&self) -> Vec<String> {
+        let mut record = vec![
+            self.snapshot_archive_path.clone(),
+            optional(&self.incremental_snapshot_archive_path),
+            self.latest_accounts_only.to_string(),
+            optional(&self.dictionary_path),
+            optional(&self.dictionary_checksum),
+            optional(&self.dictionary_slot),
+            self.snapshot_slot.to_string(),
+            self.codec.to_string(),
+            optional(&self.level),
+            self.partition.clone(),
+            optional(&self.max_number_of_accounts),
+            self.accounts.to_string(),
+            self.accounts_with_dict.to_string(),
+            self.accounts_without_dict.to_string(),
+            self.accounts_per_level
+                .iter()
+                .map(|accounts| accounts.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            self.compression_errors.to_string(),
+            self.decompression_errors.to_string(),
+            self.total_size_uncompressed.to_string(),
+            self.total_size_compressed.to_string(),
+            self.compression_ratio.to_string(),
+            self.compression_ms.to_string(),
+            self.decompression_ms.to_string(),
+            optional(&self.compression_throughput),
+            optional(&self.decompression_throughput),
+        ];
+        record.extend(self.compression_latency_us.record());
+        record.extend(self.decompression_latency_us.record());
+        record
+    }
+}
 
 pub fn main() -> anyhow::Result<()> {
     env_logger::init_from_env(
@@ -46,170 +245,196 @@ pub fn main() -> anyhow::Result<()> {
 
     let Args {
         snapshot_archive_path,
+        incremental_snapshot_archive_path,
+        latest_accounts_only,
+        dedup_memory_budget,
         dictionary,
+        codec,
         lz4_compression,
+        zstd_level,
         max_number_of_accounts,
+        partition,
+        report_format,
+        report_path,
+        breakdown_top_owners,
+        breakdown_path,
+        breakdown_format,
+        threads,
     } = args;
+    let codec = codec.unwrap_or(match lz4_compression {
+        Some(_) => CodecKind::Lz4,
+        None => CodecKind::Lz4Flex,
+    });
+    if codec != CodecKind::Lz4 && lz4_compression.is_some() {
+        log::warn!("lz4 acceleration is ignored by the {codec} codec");
+    }
+    let dictionary_path = dictionary;
     // loading dictionary
-    let dictionary = match dictionary {
+    let (dictionary, dictionary_checksum) = match &dictionary_path {
         Some(dictionary_path) => {
-            let dictionary_binary = std::fs::read(dictionary_path).unwrap();
-            let dictionary = bincode::deserialize::<DictionaryMap>(&dictionary_binary).unwrap();
-            drop(dictionary_binary);
-            dictionary
+            let (dictionary, checksum) =
+                DictionaryBundle::load_with_checksum(Path::new(dictionary_path))?;
+            (dictionary, Some(checksum))
         }
-        None => DictionaryMap::new(),
+        None => (
+            DictionaryBundle::new(codec, vec![KeyScheme::GLOBAL], None),
+            None,
+        ),
     };
-    let any_key_in_dict = dictionary.keys().find_or_first(|_| true); // get any key
-    let nb_bits = any_key_in_dict.map(|x| x.nb_bits).unwrap_or_default();
+    if dictionary.codec != codec {
+        log::warn!(
+            "dictionaries were trained for {} but are used with {codec}",
+            dictionary.codec
+        );
+    }
+    if let Some(source) = &dictionary.source {
+        log::info!("dictionaries trained on snapshot {source:?}");
+    }
+    log::info!("dictionary key schemes {:?}", dictionary.key_schemes);
+    let split = dictionary.split;
+    let partition = partition.unwrap_or(match split {
+        Some(_) => Partition::Test,
+        None => Partition::All,
+    });
+    let source = dictionary.source;
+    let threads = threads.unwrap_or_else(num_cpus::get);
+
+    let level = match codec {
+        CodecKind::Lz4 => lz4_compression,
+        CodecKind::Zstd => Some(zstd_level),
+        CodecKind::None | CodecKind::Lz4Flex => None,
+    };
+    if !codec.supports_dictionary() && !dictionary.is_empty() {
+        log::warn!("{codec} codec does not support dictionaries, they will be ignored");
+    }
 
     let archive_path = PathBuf::from_str(snapshot_archive_path.as_str()).unwrap();
 
-    let mut loader: ArchiveSnapshotExtractor<File> =
-        ArchiveSnapshotExtractor::open(&archive_path).unwrap();
-
-    let mut total_size_uncompressed: usize = 0;
-    let mut total_size_compressed: usize = 0;
-    let mut accounts_with_dict: usize = 0;
-    let mut accounts_without_dict: usize = 0;
-    let mut compression_errors: usize = 0;
-    let mut decompression_errors: usize = 0;
-    let mut time_compression: Duration = Duration::from_micros(0);
-    let mut time_decompression: Duration = Duration::from_micros(0);
-    let mut account_total: u64 = 0;
-    let mut compress_out_buffer = vec![0; 16 * 1024 * 1024]; // 16 MB buffer
-
-    let max_number_of_accounts = max_number_of_accounts.unwrap_or(u64::MAX);
-    for vec in loader.iter() {
-        let append_vec = vec.unwrap();
-        // info!("size: {:?}", append_vec.len());
-        for handle in append_vec_iter(&append_vec) {
-            let stored = handle.access().unwrap();
-            if stored.meta.data_len < 64 {
-                continue;
-            }
+    let incremental_archive_path = incremental_snapshot_archive_path
+        .as_ref()
+        .map(|incremental_archive_path| PathBuf::from_str(incremental_archive_path).unwrap());
+    let progress_tracking = LoggingReadProgressTracking::default();
+    // the latest accounts index also drops the accounts overwritten by the incremental snapshot,
+    // which is then read once to build it and once to iterate
+    let open_loader = || {
+        SnapshotArchivesExtractor::open(
+            &archive_path,
+            incremental_archive_path.as_deref(),
+            false,
+            &progress_tracking,
+        )
+        .unwrap()
+    };
 
-            let owner_partial_pubkey_by_bits =
-                PartialPubkeyByBits::new(stored.account_meta.owner, nb_bits);
-            let dict_iter = dictionary.get(&owner_partial_pubkey_by_bits);
-            account_total += 1;
-            if account_total > max_number_of_accounts {
-                break;
+    let mut loader: SnapshotArchivesExtractor;
+    let mut latest_accounts_loader: LatestAccountsExtractor<SnapshotArchivesExtractor>;
+    let (manifest, append_vecs) = if latest_accounts_only {
+        let index = LatestAccountsIndex::build(&mut open_loader(), dedup_memory_budget).unwrap();
+        latest_accounts_loader = LatestAccountsExtractor::new(open_loader(), index);
+        (
+            latest_accounts_loader.manifest().clone(),
+            latest_accounts_loader.iter(),
+        )
+    } else {
+        loader = open_loader();
+        (loader.manifest().clone(), loader.iter())
+    };
+    log::info!("snapshot manifest: {manifest:?}");
+    match source {
+        Some(source) if source.slot == manifest.slot => println!(
+            "evaluating {partition} accounts of the training snapshot at slot {}",
+            manifest.slot
+        ),
+        Some(source) => {
+            println!(
+                "evaluating {partition} accounts of snapshot at slot {}, dictionaries trained on slot {}",
+                manifest.slot, source.slot
+            );
+            if manifest.slot < source.slot {
+                log::warn!("evaluated snapshot is older than the training snapshot");
             }
+        }
+        None => println!(
+            "evaluating {partition} accounts of snapshot at slot {}",
+            manifest.slot
+        ),
+    }
+    if split.is_none() && partition != Partition::All {
+        log::warn!("dictionaries were trained without holdout, evaluating all accounts");
+    }
 
-            log::debug!("{account_total:?}",);
-            let compressed = match dict_iter {
-                Some(dict_data) => {
-                    accounts_with_dict += 1;
-                    let instant = Instant::now();
-                    let len = lz4_flex::block::compress_into_with_dict(
-                        stored.data,
-                        &mut compress_out_buffer,
-                        dict_data,
-                    )
-                    .unwrap();
-                    time_compression += instant.elapsed();
-                    total_size_compressed += len;
-                    total_size_uncompressed += stored.meta.data_len as usize;
-                    compress_out_buffer[0..len].to_vec()
+    // the limit is applied while iterating so that the same accounts are evaluated whatever
+    // the number of threads
+    let append_vecs: AppendVecIterator = match max_number_of_accounts {
+        Some(max_number_of_accounts) => {
+            let mut remaining = max_number_of_accounts;
+            Box::new(append_vecs.map_while(move |append_vec| {
+                if remaining == 0 {
+                    return None;
                 }
-                None => {
-                    accounts_without_dict += 1;
-                    let instant = Instant::now();
-                    match lz4_compression {
-                        Some(speed) => {
-                            match lz4::block::compress(
-                                stored.data,
-                                Some(CompressionMode::FAST(speed)),
-                                true,
-                            ) {
-                                Ok(data) => {
-                                    time_compression += instant.elapsed();
-                                    total_size_compressed += data.len();
-                                    total_size_uncompressed += stored.meta.data_len as usize;
-                                    data
-                                }
-                                Err(e) => {
-                                    log::error!("error lz4 compression {e:?}");
-                                    compression_errors += 1;
-                                    continue;
-                                }
-                            }
-                        }
-                        None => {
-                            let len =
-                                lz4_flex::compress_into(stored.data, &mut compress_out_buffer)
-                                    .unwrap();
-                            time_compression += instant.elapsed();
-                            total_size_compressed += len;
-                            total_size_uncompressed += stored.meta.data_len as usize;
-                            compress_out_buffer[..len].to_vec()
-                        }
-                    }
+                let append_vec = match append_vec {
+                    Ok(append_vec) => append_vec,
+                    Err(e) => return Some(Err(e)),
+                };
+                let evaluated = append_vec_iter(&append_vec)
+                    .filter(|handle| is_evaluated(&handle.access().unwrap(), split, partition))
+                    .count() as u64;
+                if evaluated <= remaining {
+                    remaining -= evaluated;
+                    return Some(Ok(append_vec));
                 }
-            };
-
-            let mut output = vec![0; stored.meta.data_len as usize];
-            let decompressed = match dict_iter {
-                Some(dictionary) => {
-                    let instant = Instant::now();
-                    match lz4_flex::block::decompress_into_with_dict(
-                        &compressed,
-                        &mut output,
-                        dictionary,
-                    ) {
-                        Ok(_) => {
-                            time_decompression += instant.elapsed();
-                            output
-                        }
-                        Err(_) => {
-                            decompression_errors += 1;
-                            continue;
-                        }
-                    }
-                }
-                None => match lz4_compression {
-                    Some(_) => {
-                        let instant = Instant::now();
-                        match lz4::block::decompress(&compressed, None) {
-                            Ok(data) => {
-                                time_decompression += instant.elapsed();
-                                data
-                            }
-                            Err(e) => {
-                                log::error!("lz4 decompression error {e:?}");
-                                decompression_errors += 1;
-                                continue;
-                            }
-                        }
-                    }
-                    None => {
-                        let instant = Instant::now();
-                        match lz4_flex::decompress_into(&compressed, &mut output) {
-                            Ok(_data) => {
-                                time_decompression += instant.elapsed();
-                                output
-                            }
-                            Err(e) => {
-                                log::error!(
-                                    "error in decompression {e} for decompressing {} bytes",
-                                    compressed.len()
-                                );
-                                decompression_errors += 1;
-                                continue;
-                            }
-                        }
-                    }
-                },
-            };
-            assert_eq!(decompressed, stored.data)
+                let mut kept = 0;
+                let append_vec = append_vec.filter(|account| {
+                    let keep = kept < remaining && is_evaluated(account, split, partition);
+                    kept += u64::from(keep);
+                    keep
+                });
+                remaining = 0;
+                Some(
+                    append_vec
+                        .map(|append_vec| append_vec.expect("at least one account is kept"))
+                        .map_err(SnapshotError::from),
+                )
+            }))
         }
-    }
-    const ONE_MB: usize = 1024 * 1024;
+        None => append_vecs,
+    };
+
+    let folder = par_fold_append_vecs(
+        append_vecs,
+        || {
+            TesterFolder::new(
+                &dictionary,
+                codec,
+                level,
+                partition,
+                breakdown_top_owners.is_some(),
+            )
+        },
+        threads,
+    )?;
+    let key_schemes = dictionary.key_schemes;
+    let TesterStats {
+        accounts: account_total,
+        accounts_with_dict,
+        accounts_without_dict,
+        accounts_per_level,
+        compression_errors,
+        decompression_errors,
+        comparison_errors,
+        compression: stats,
+        breakdown,
+    } = folder.into_stats();
+
+    const ONE_MB: u64 = 1024 * 1024;
+    let throughput = |throughput: Option<f64>| match throughput {
+        Some(throughput) => format!("{throughput:.3}"),
+        None => "-".to_string(),
+    };
     println!(
-        "After lz4 compression and decompression with dictionary \n \
-     {total_size_compressed} - ({} MBs) total bytes for lz compressed data, \n \
-     {total_size_uncompressed} - ({} MBs) total bytes before compression, achieving\n \
+        "After {codec:?} compression and decompression with dictionary \n \
+     {} - ({} MBs) total bytes for compressed data, \n \
+     {} - ({} MBs) total bytes before compression, achieving\n \
      {} ({}) compression ratio, \n \
      {} ms time required to compress all data, \n \
      {} ms to decompress all data, \n \
@@ -217,17 +442,112 @@ pub fn main() -> anyhow::Result<()> {
      {accounts_without_dict} accounts did not use dictionary, \n \
      {compression_errors} compression errors, \n \
      {decompression_errors} decompression errors \n \
-     {} GBps compression speed \n\
-     {} GBps decompression speed \n",
-        total_size_compressed / ONE_MB,
-        total_size_uncompressed / ONE_MB,
-        (total_size_compressed as f64 / total_size_uncompressed as f64),
-        (total_size_uncompressed as f64 / total_size_compressed as f64),
-        time_compression.as_millis(),
-        time_decompression.as_millis(),
-        (total_size_uncompressed / ONE_MB) as f64 / (time_compression.as_millis() as f64),
-        (total_size_uncompressed / ONE_MB) as f64 / (time_decompression.as_millis() as f64),
+     {} GB/s compression speed \n\
+     {} GB/s decompression speed \n",
+        stats.compressed_bytes,
+        stats.compressed_bytes / ONE_MB,
+        stats.uncompressed_bytes,
+        stats.uncompressed_bytes / ONE_MB,
+        (stats.compressed_bytes as f64 / stats.uncompressed_bytes as f64),
+        stats.compression_ratio(),
+        stats.compression.total().as_millis(),
+        stats.decompression.total().as_millis(),
+        throughput(stats.compression_throughput()),
+        throughput(stats.decompression_throughput()),
     );
+    for (name, histogram) in [
+        ("compression", &stats.compression),
+        ("decompression", &stats.decompression),
+    ] {
+        let summary = histogram.summary();
+        println!(
+            "{name} latency per account: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            summary.p50, summary.p90, summary.p99, summary.max
+        );
+    }
+    println!("compression ratio by data length");
+    for bucket in stats.size_buckets() {
+        println!(
+            "{:>9} - {:>9} bytes {:>12} accounts {:>14} bytes {:>14} compressed {:.3}",
+            bucket.min_data_len,
+            bucket.max_data_len,
+            bucket.accounts,
+            bucket.uncompressed_bytes,
+            bucket.compressed_bytes,
+            bucket.compression_ratio()
+        );
+    }
+    for (key_scheme, accounts) in key_schemes.iter().zip(&accounts_per_level) {
+        println!("{accounts} accounts resolved with {key_scheme:?}");
+    }
+
+    if let Some(top_owners) = breakdown_top_owners {
+        let rows = breakdown.rows(top_owners);
+        if comparison_errors > 0 {
+            log::warn!(
+                "{comparison_errors} accounts are left out of the breakdown, they could not be \
+                 compressed without dictionary"
+            );
+        }
+        match breakdown_path {
+            Some(breakdown_path) => breakdown_format
+                .unwrap_or(ReportFormat::from_path(&breakdown_path))
+                .write(BufWriter::new(File::create(&breakdown_path)?), &rows)?,
+            None => {
+                println!("gain of the dictionaries over {codec} without dictionary");
+                for row in &rows {
+                    println!(
+                        "{:>13} {:>12} accounts {:>14} bytes {:>14} compressed {:>14} plain {:>12} gain ({:.3}x) {}",
+                        row.group,
+                        row.accounts,
+                        row.raw_bytes,
+                        row.compressed_bytes,
+                        row.plain_compressed_bytes,
+                        row.gain_bytes,
+                        row.gain_ratio,
+                        row.name
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(report_path) = report_path {
+        let tester_report = TesterReport {
+            snapshot_archive_path,
+            incremental_snapshot_archive_path,
+            latest_accounts_only,
+            dictionary_path,
+            dictionary_checksum,
+            dictionary_slot: source.map(|source| source.slot),
+            snapshot_slot: manifest.slot,
+            codec,
+            level,
+            partition: partition.to_string(),
+            max_number_of_accounts,
+            accounts: account_total,
+            accounts_with_dict,
+            accounts_without_dict,
+            accounts_per_level,
+            compression_errors,
+            decompression_errors,
+            total_size_uncompressed: stats.uncompressed_bytes,
+            total_size_compressed: stats.compressed_bytes,
+            compression_ratio: stats.compression_ratio(),
+            compression_ms: stats.compression.total().as_secs_f64() * 1000.0,
+            decompression_ms: stats.decompression.total().as_secs_f64() * 1000.0,
+            compression_throughput: stats.compression_throughput(),
+            decompression_throughput: stats.decompression_throughput(),
+            compression_latency_us: LatencyReport::new(&stats.compression.summary()),
+            decompression_latency_us: LatencyReport::new(&stats.decompression.summary()),
+        };
+        report_format
+            .unwrap_or(ReportFormat::from_path(&report_path))
+            .write(
+                BufWriter::new(File::create(&report_path)?),
+                &[tester_report],
+            )?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,241 @@
+use {
+    crate::{dictionary_key::DictionaryKey, report::CsvRecord},
+    serde::Serialize,
+    solana_sdk::pubkey::Pubkey,
+    std::{collections::HashMap, fmt::Display, time::Duration},
+};
+
+/// Compression of a group of accounts with their dictionary and with the same codec without
+/// dictionary.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BreakdownStats {
+    pub accounts: u64,
+    pub raw_bytes: u64,
+    pub compressed_bytes: u64,
+    /// Compressed bytes without dictionary.
+    pub plain_compressed_bytes: u64,
+    pub compression_time: Duration,
+    pub plain_compression_time: Duration,
+}
+
+impl BreakdownStats {
+    pub fn merge(&mut self, other: &Self) {
+        self.accounts += other.accounts;
+        self.raw_bytes += other.raw_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+        self.plain_compressed_bytes += other.plain_compressed_bytes;
+        self.compression_time += other.compression_time;
+        self.plain_compression_time += other.plain_compression_time;
+    }
+
+    /// Bytes saved by the dictionary, negative when it makes compression worse.
+    pub const fn gain(&self) -> i64 {
+        self.plain_compressed_bytes as i64 - self.compressed_bytes as i64
+    }
+}
+
+/// Group of accounts of a breakdown row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BreakdownGroup {
+    /// Accounts resolved to a dictionary key.
+    Key,
+    /// Accounts without dictionary.
+    NoDictionary,
+    Owner,
+}
+
+impl BreakdownGroup {
+    pub const fn name(self) -> &'static str {
+        match self {
+            BreakdownGroup::Key => "key",
+            BreakdownGroup::NoDictionary => "no-dictionary",
+            BreakdownGroup::Owner => "owner",
+        }
+    }
+}
+
+impl Display for BreakdownGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Compression per dictionary key and per owner.
+#[derive(Default)]
+pub struct CompressionBreakdown {
+    /// `None` for accounts without dictionary.
+    pub per_key: HashMap<Option<DictionaryKey>, BreakdownStats>,
+    pub per_owner: HashMap<Pubkey, BreakdownStats>,
+}
+
+impl CompressionBreakdown {
+    /// Adds the compression of one account.
+    pub fn add(&mut self, key: Option<DictionaryKey>, owner: Pubkey, account: &BreakdownStats) {
+        self.per_key.entry(key).or_default().merge(account);
+        self.per_owner.entry(owner).or_default().merge(account);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (key, stats) in &other.per_key {
+            self.per_key.entry(*key).or_default().merge(stats);
+        }
+        for (owner, stats) in &other.per_owner {
+            self.per_owner.entry(*owner).or_default().merge(stats);
+        }
+    }
+
+    /// Rows of every key and of the `top_owners` owners with the most raw bytes, each group
+    /// sorted by decreasing gain.
+    pub fn rows(&self, top_owners: usize) -> Vec<BreakdownRow> {
+        let mut keys = self
+            .per_key
+            .iter()
+            .map(|(key, stats)| match key {
+                Some(key) => BreakdownRow::new(BreakdownGroup::Key, key.to_string(), stats),
+                None => BreakdownRow::new(BreakdownGroup::NoDictionary, String::new(), stats),
+            })
+            .collect::<Vec<_>>();
+        keys.sort_by(BreakdownRow::cmp_gain);
+
+        let mut owners = self.per_owner.iter().collect::<Vec<_>>();
+        owners.sort_unstable_by_key(|(owner, stats)| (std::cmp::Reverse(stats.raw_bytes), **owner));
+        let mut owners = owners
+            .into_iter()
+            .take(top_owners)
+            .map(|(owner, stats)| {
+                BreakdownRow::new(BreakdownGroup::Owner, owner.to_string(), stats)
+            })
+            .collect::<Vec<_>>();
+        owners.sort_by(BreakdownRow::cmp_gain);
+
+        keys.extend(owners);
+        keys
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BreakdownRow {
+    pub group: BreakdownGroup,
+    /// Dictionary key or owner.
+    pub name: String,
+    pub accounts: u64,
+    pub raw_bytes: u64,
+    pub compressed_bytes: u64,
+    pub plain_compressed_bytes: u64,
+    pub gain_bytes: i64,
+    /// Plain compressed size over compressed size with dictionary.
+    pub gain_ratio: f64,
+    pub compression_ms: f64,
+    pub plain_compression_ms: f64,
+}
+
+impl BreakdownRow {
+    pub fn new(group: BreakdownGroup, name: String, stats: &BreakdownStats) -> Self {
+        Self {
+            group,
+            name,
+            accounts: stats.accounts,
+            raw_bytes: stats.raw_bytes,
+            compressed_bytes: stats.compressed_bytes,
+            plain_compressed_bytes: stats.plain_compressed_bytes,
+            gain_bytes: stats.gain(),
+            gain_ratio: stats.plain_compressed_bytes as f64 / stats.compressed_bytes as f64,
+            compression_ms: stats.compression_time.as_secs_f64() * 1000.0,
+            plain_compression_ms: stats.plain_compression_time.as_secs_f64() * 1000.0,
+        }
+    }
+
+    /// Largest gain first, then by name.
+    fn cmp_gain(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .gain_bytes
+            .cmp(&self.gain_bytes)
+            .then_with(|| self.name.cmp(&other.name))
+    }
+}
+
+impl CsvRecord for BreakdownRow {
+    const HEADER: &'static [&'static str] = &[
+        "group",
+        "name",
+        "accounts",
+        "raw_bytes",
+        "compressed_bytes",
+        "plain_compressed_bytes",
+        "gain_bytes",
+        "gain_ratio",
+        "compression_ms",
+        "plain_compression_ms",
+    ];
+
+    fn record(&self) -> Vec<String> {
+        vec![
+            self.group.to_string(),
+            self.name.clone(),
+            self.accounts.to_string(),
+            self.raw_bytes.to_string(),
+            self.compressed_bytes.to_string(),
+            self.plain_compressed_bytes.to_string(),
+            self.gain_bytes.to_string(),
+            format!("{:.4}", self.gain_ratio),
+            format!("{:.3}", self.compression_ms),
+            format!("{:.3}", self.plain_compression_ms),
+        ]
+    }
+}
+
+#[test]
+fn test_compression_breakdown() {
+    use crate::partial_pubkey_by_bits::PartialPubkeyByBits;
+
+    let owners = [
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    ];
+    let key = |owner| Some(DictionaryKey::owner(PartialPubkeyByBits::new(owner, 256)));
+    let account = |raw_bytes, compressed_bytes, plain_compressed_bytes| BreakdownStats {
+        accounts: 1,
+        raw_bytes,
+        compressed_bytes,
+        plain_compressed_bytes,
+        ..BreakdownStats::default()
+    };
+    let mut breakdown = CompressionBreakdown::default();
+    // the dictionary of the first owner helps, the one of the second regresses
+    breakdown.add(key(owners[0]), owners[0], &account(100, 20, 60));
+    breakdown.add(key(owners[0]), owners[0], &account(100, 30, 60));
+    breakdown.add(key(owners[1]), owners[1], &account(1000, 500, 400));
+    let mut other = CompressionBreakdown::default();
+    other.add(None, owners[2], &account(10, 8, 8));
+    breakdown.merge(&other);
+
+    let rows = breakdown.rows(2);
+    let summary = rows
+        .iter()
+        .map(|row| (row.group, row.name.clone(), row.accounts, row.gain_bytes))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            (
+                BreakdownGroup::Key,
+                key(owners[0]).unwrap().to_string(),
+                2,
+                70
+            ),
+            (BreakdownGroup::NoDictionary, String::new(), 1, 0),
+            (
+                BreakdownGroup::Key,
+                key(owners[1]).unwrap().to_string(),
+                1,
+                -100
+            ),
+            (BreakdownGroup::Owner, owners[0].to_string(), 2, 70),
+            (BreakdownGroup::Owner, owners[1].to_string(), 1, -100),
+        ]
+    );
+    assert_eq!(rows[0].name, owners[0].to_string());
+    assert_eq!(format!("{:>7}|", BreakdownGroup::Key), "    key|");
+}
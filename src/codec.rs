@@ -0,0 +1,355 @@
+use {
+    lz4::block::CompressionMode,
+    serde::{Deserialize, Serialize},
+    std::{fmt::Display, str::FromStr},
+    thiserror::Error,
+    zstd::{
+        dict::{DecoderDictionary, EncoderDictionary},
+        zstd_safe::{CCtx, DCtx},
+    },
+};
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("{0}")]
+    IOError(#[from] std::io::Error),
+    #[error("lz4_flex compression failed: {0}")]
+    Lz4FlexCompress(lz4_flex::block::CompressError),
+    #[error("lz4_flex decompression failed: {0}")]
+    Lz4FlexDecompress(lz4_flex::block::DecompressError),
+    #[error("zstd error: {0}")]
+    Zstd(&'static str),
+    #[error("Output buffer too small, {required} bytes required but got {available}")]
+    OutputTooSmall { required: usize, available: usize },
+    #[error("Codec {0} does not support dictionaries")]
+    DictionaryNotSupported(CodecKind),
+    #[error("Unknown codec {0}")]
+    UnknownCodec(String),
+}
+
+pub type CodecResult<T> = Result<T, CodecError>;
+
+/// Block compression of a single account's data.
+///
+/// A codec instance is bound to at most one dictionary, use one instance per dictionary key.
+pub trait AccountCodec: Send {
+    fn kind(&self) -> CodecKind;
+
+    /// Codec parameters as a human readable string, e.g. `level=3`.
+    fn params(&self) -> String;
+
+    fn has_dictionary(&self) -> bool;
+
+    /// Upper bound of the compressed size of `uncompressed_len` bytes.
+    fn max_compressed_len(&self, uncompressed_len: usize) -> usize;
+
+    /// Compresses `input` into `output`, returns the number of bytes written.
+    fn compress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize>;
+
+    /// Decompresses `input` into `output`, which must be exactly as long as the uncompressed
+    /// data. Returns the number of bytes written.
+    fn decompress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize>;
+
+    fn name(&self) -> &'static str {
+        self.kind().name()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CodecKind {
+    None,
+    Lz4,
+    Lz4Flex,
+    Zstd,
+}
+
+impl CodecKind {
+    pub const ALL: [CodecKind; 4] = [
+        CodecKind::None,
+        CodecKind::Lz4,
+        CodecKind::Lz4Flex,
+        CodecKind::Zstd,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            CodecKind::None => "none",
+            CodecKind::Lz4 => "lz4",
+            CodecKind::Lz4Flex => "lz4-flex",
+            CodecKind::Zstd => "zstd",
+        }
+    }
+
+    pub const fn supports_dictionary(self) -> bool {
+        matches!(self, CodecKind::Lz4Flex | CodecKind::Zstd)
+    }
+
+    /// Whether the codec is tuned with a level, see [`CodecKind::default_level`].
+    pub const fn has_level(self) -> bool {
+        matches!(self, CodecKind::Lz4 | CodecKind::Zstd)
+    }
+
+    /// Level used when none is given: acceleration for lz4, compression level for zstd.
+    pub const fn default_level(self) -> i32 {
+        match self {
+            CodecKind::Lz4 => 1,
+            CodecKind::Zstd => zstd::DEFAULT_COMPRESSION_LEVEL,
+            CodecKind::None | CodecKind::Lz4Flex => 0,
+        }
+    }
+
+    /// Creates a codec instance, `level` is ignored by codecs without levels.
+    pub fn create(
+        self,
+        level: Option<i32>,
+        dictionary: Option<&[u8]>,
+    ) -> CodecResult<Box<dyn AccountCodec>> {
+        if dictionary.is_some() && !self.supports_dictionary() {
+            return Err(CodecError::DictionaryNotSupported(self));
+        }
+        let level = level.unwrap_or(self.default_level());
+        Ok(match self {
+            CodecKind::None => Box::new(NoneCodec {}),
+            CodecKind::Lz4 => Box::new(Lz4Codec {
+                acceleration: level,
+            }),
+            CodecKind::Lz4Flex => Box::new(Lz4FlexCodec {
+                dictionary: dictionary.map(|d| d.to_vec()),
+            }),
+            CodecKind::Zstd => Box::new(ZstdCodec::new(level, dictionary)),
+        })
+    }
+}
+
+impl Display for CodecKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CodecKind {
+    type Err = CodecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CodecKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| CodecError::UnknownCodec(s.to_string()))
+    }
+}
+
+const fn check_output_len(required: usize, available: usize) -> CodecResult<()> {
+    if available < required {
+        Err(CodecError::OutputTooSmall {
+            required,
+            available,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Stores the data as is, useful as a baseline.
+pub struct NoneCodec {}
+
+impl AccountCodec for NoneCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::None
+    }
+
+    fn params(&self) -> String {
+        String::new()
+    }
+
+    fn has_dictionary(&self) -> bool {
+        false
+    }
+
+    fn max_compressed_len(&self, uncompressed_len: usize) -> usize {
+        uncompressed_len
+    }
+
+    fn compress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        check_output_len(input.len(), output.len())?;
+        output[..input.len()].copy_from_slice(input);
+        Ok(input.len())
+    }
+
+    fn decompress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        self.compress_into(input, output)
+    }
+}
+
+/// lz4 C block api, the uncompressed size is not stored in the block.
+pub struct Lz4Codec {
+    acceleration: i32,
+}
+
+impl AccountCodec for Lz4Codec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Lz4
+    }
+
+    fn params(&self) -> String {
+        format!("acceleration={}", self.acceleration)
+    }
+
+    fn has_dictionary(&self) -> bool {
+        false
+    }
+
+    fn max_compressed_len(&self, uncompressed_len: usize) -> usize {
+        lz4::block::compress_bound(uncompressed_len).unwrap_or(usize::MAX)
+    }
+
+    fn compress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        Ok(lz4::block::compress_to_buffer(
+            input,
+            Some(CompressionMode::FAST(self.acceleration)),
+            false,
+            output,
+        )?)
+    }
+
+    fn decompress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        Ok(lz4::block::decompress_to_buffer(
+            input,
+            Some(output.len() as i32),
+            output,
+        )?)
+    }
+}
+
+/// lz4_flex block compression, the dictionary is used as raw content.
+pub struct Lz4FlexCodec {
+    dictionary: Option<Vec<u8>>,
+}
+
+impl AccountCodec for Lz4FlexCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Lz4Flex
+    }
+
+    fn params(&self) -> String {
+        String::new()
+    }
+
+    fn has_dictionary(&self) -> bool {
+        self.dictionary.is_some()
+    }
+
+    fn max_compressed_len(&self, uncompressed_len: usize) -> usize {
+        lz4_flex::block::get_maximum_output_size(uncompressed_len)
+    }
+
+    fn compress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        match &self.dictionary {
+            Some(dictionary) => lz4_flex::block::compress_into_with_dict(input, output, dictionary),
+            None => lz4_flex::block::compress_into(input, output),
+        }
+        .map_err(CodecError::Lz4FlexCompress)
+    }
+
+    fn decompress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        match &self.dictionary {
+            Some(dictionary) => {
+                lz4_flex::block::decompress_into_with_dict(input, output, dictionary)
+            }
+            None => lz4_flex::block::decompress_into(input, output),
+        }
+        .map_err(CodecError::Lz4FlexDecompress)
+    }
+}
+
+/// zstd single frame compression, the dictionary is prepared once and reused for every account.
+pub struct ZstdCodec {
+    level: i32,
+    compression_context: CCtx<'static>,
+    decompression_context: DCtx<'static>,
+    dictionary: Option<(EncoderDictionary<'static>, DecoderDictionary<'static>)>,
+}
+
+impl ZstdCodec {
+    pub fn new(level: i32, dictionary: Option<&[u8]>) -> Self {
+        Self {
+            level,
+            compression_context: CCtx::create(),
+            decompression_context: DCtx::create(),
+            dictionary: dictionary.map(|dictionary| {
+                (
+                    EncoderDictionary::copy(dictionary, level),
+                    DecoderDictionary::copy(dictionary),
+                )
+            }),
+        }
+    }
+}
+
+impl AccountCodec for ZstdCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Zstd
+    }
+
+    fn params(&self) -> String {
+        format!("level={}", self.level)
+    }
+
+    fn has_dictionary(&self) -> bool {
+        self.dictionary.is_some()
+    }
+
+    fn max_compressed_len(&self, uncompressed_len: usize) -> usize {
+        zstd::zstd_safe::compress_bound(uncompressed_len)
+    }
+
+    fn compress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        match &self.dictionary {
+            Some((encoder_dictionary, _)) => self.compression_context.compress_using_cdict(
+                output,
+                input,
+                encoder_dictionary.as_cdict(),
+            ),
+            None => self.compression_context.compress(output, input, self.level),
+        }
+        .map_err(|code| CodecError::Zstd(zstd::zstd_safe::get_error_name(code)))
+    }
+
+    fn decompress_into(&mut self, input: &[u8], output: &mut [u8]) -> CodecResult<usize> {
+        match &self.dictionary {
+            Some((_, decoder_dictionary)) => self.decompression_context.decompress_using_ddict(
+                output,
+                input,
+                decoder_dictionary.as_ddict(),
+            ),
+            None => self.decompression_context.decompress(output, input),
+        }
+        .map_err(|code| CodecError::Zstd(zstd::zstd_safe::get_error_name(code)))
+    }
+}
+
+#[test]
+fn test_codecs_roundtrip() {
+    let data = (0..4096u32)
+        .flat_map(|i| (i % 17).to_le_bytes())
+        .collect::<Vec<u8>>();
+    let dictionary = data[..1024].to_vec();
+    for kind in CodecKind::ALL {
+        let mut codecs = vec![kind.create(None, None).unwrap()];
+        if kind.supports_dictionary() {
+            codecs.push(kind.create(None, Some(&dictionary)).unwrap());
+        } else {
+            assert!(kind.create(None, Some(&dictionary)).is_err());
+        }
+        for mut codec in codecs {
+            let mut compressed = vec![0; codec.max_compressed_len(data.len())];
+            let len = codec.compress_into(&data, &mut compressed).unwrap();
+            let mut decompressed = vec![0; data.len()];
+            codec
+                .decompress_into(&compressed[..len], &mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, data, "{} {}", codec.name(), codec.params());
+        }
+        assert_eq!(kind.name().parse::<CodecKind>().unwrap(), kind);
+    }
+}
@@ -0,0 +1,288 @@
+use {
+    serde::Serialize,
+    std::{collections::BTreeMap, time::Duration},
+};
+
+/// Sub buckets per power of two of a [`LatencyHistogram`], percentiles are within 12.5%.
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// Log-linear histogram of durations in nanoseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    const fn bucket(nanos: u64) -> usize {
+        if nanos < SUB_BUCKETS {
+            return nanos as usize;
+        }
+        let shift = 63 - nanos.leading_zeros() - SUB_BUCKET_BITS;
+        (SUB_BUCKETS * (shift as u64 + 1) + ((nanos >> shift) & (SUB_BUCKETS - 1))) as usize
+    }
+
+    /// Largest duration of a bucket in nanoseconds.
+    const fn bucket_upper_bound(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+        let shift = bucket / SUB_BUCKETS - 1;
+        let lower = (SUB_BUCKETS + bucket % SUB_BUCKETS) << shift;
+        lower + ((1 << shift) - 1)
+    }
+
+    pub fn add(&mut self, duration: Duration) {
+        let bucket = Self::bucket(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX));
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    pub const fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of all the durations.
+    pub const fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub const fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Duration below which `quantile` of the durations are, zero when empty.
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let upper_bound = Duration::from_nanos(Self::bucket_upper_bound(bucket));
+                return upper_bound.min(self.max);
+            }
+        }
+        Duration::ZERO
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            p50: self.percentile(0.5),
+            p90: self.percentile(0.9),
+            p99: self.percentile(0.99),
+            max: self.max,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Compression of the accounts whose data length is in `[min_data_len, max_data_len]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SizeBucket {
+    pub min_data_len: u64,
+    pub max_data_len: u64,
+    pub accounts: u64,
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+}
+
+impl SizeBucket {
+    pub fn compression_ratio(&self) -> f64 {
+        self.uncompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+}
+
+/// Sizes and latencies of compressing and decompressing accounts one by one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub accounts: u64,
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+    pub compression: LatencyHistogram,
+    pub decompression: LatencyHistogram,
+    /// Accounts by power of two of their data length.
+    sizes: BTreeMap<u32, SizeBucket>,
+}
+
+impl CompressionStats {
+    /// Adds an account compressed and decompressed back.
+    pub fn add(
+        &mut self,
+        data_len: usize,
+        compressed_len: usize,
+        compression_time: Duration,
+        decompression_time: Duration,
+    ) {
+        self.accounts += 1;
+        self.uncompressed_bytes += data_len as u64;
+        self.compressed_bytes += compressed_len as u64;
+        self.compression.add(compression_time);
+        self.decompression.add(decompression_time);
+
+        let bits = u64::BITS - (data_len as u64).leading_zeros();
+        let bucket = self.sizes.entry(bits).or_insert_with(|| {
+            let max_data_len = u64::MAX.checked_shr(u64::BITS - bits).unwrap_or(0);
+            SizeBucket {
+                min_data_len: max_data_len - (max_data_len >> 1),
+                max_data_len,
+                ..SizeBucket::default()
+            }
+        });
+        bucket.accounts += 1;
+        bucket.uncompressed_bytes += data_len as u64;
+        bucket.compressed_bytes += compressed_len as u64;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.accounts += other.accounts;
+        self.uncompressed_bytes += other.uncompressed_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+        self.compression.merge(&other.compression);
+        self.decompression.merge(&other.decompression);
+        for (bits, other) in &other.sizes {
+            let bucket = self.sizes.entry(*bits).or_insert(SizeBucket {
+                accounts: 0,
+                uncompressed_bytes: 0,
+                compressed_bytes: 0,
+                ..*other
+            });
+            bucket.accounts += other.accounts;
+            bucket.uncompressed_bytes += other.uncompressed_bytes;
+            bucket.compressed_bytes += other.compressed_bytes;
+        }
+    }
+
+    pub fn compression_ratio(&self) -> f64 {
+        self.uncompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+
+    /// Uncompressed GB compressed per second, `None` when no time was measured.
+    pub fn compression_throughput(&self) -> Option<f64> {
+        throughput(self.uncompressed_bytes, self.compression.total())
+    }
+
+    /// Uncompressed GB produced per second, `None` when no time was measured.
+    pub fn decompression_throughput(&self) -> Option<f64> {
+        throughput(self.uncompressed_bytes, self.decompression.total())
+    }
+
+    /// Non empty size buckets by increasing data length.
+    pub fn size_buckets(&self) -> impl Iterator<Item = &SizeBucket> {
+        self.sizes.values()
+    }
+}
+
+/// GB (10^9 bytes) per second.
+pub fn throughput(bytes: u64, duration: Duration) -> Option<f64> {
+    (!duration.is_zero()).then(|| bytes as f64 / duration.as_secs_f64() / 1e9)
+}
+
+#[test]
+fn test_latency_histogram() {
+    for nanos in [0, 1, 7, 8, 9, 15, 16, 17, 1000, 123_456_789, u64::MAX] {
+        let bucket = LatencyHistogram::bucket(nanos);
+        let upper_bound = LatencyHistogram::bucket_upper_bound(bucket);
+        assert!(upper_bound >= nanos);
+        assert!(upper_bound - nanos <= nanos / SUB_BUCKETS);
+        if bucket > 0 {
+            assert!(LatencyHistogram::bucket_upper_bound(bucket - 1) < nanos);
+        }
+    }
+
+    let mut histogram = LatencyHistogram::default();
+    assert_eq!(histogram.percentile(0.5), Duration::ZERO);
+    for micros in 1..=100 {
+        histogram.add(Duration::from_micros(micros));
+    }
+    let mut other = LatencyHistogram::default();
+    other.add(Duration::from_millis(10));
+    histogram.merge(&other);
+    assert_eq!(histogram.count(), 101);
+    let summary = histogram.summary();
+    let within = |duration: Duration, expected: Duration| {
+        duration >= expected && duration <= expected + expected / SUB_BUCKETS as u32
+    };
+    assert!(
+        within(summary.p50, Duration::from_micros(51)),
+        "{summary:?}"
+    );
+    assert!(
+        within(summary.p90, Duration::from_micros(91)),
+        "{summary:?}"
+    );
+    assert!(
+        within(summary.p99, Duration::from_micros(100)),
+        "{summary:?}"
+    );
+    assert_eq!(summary.max, Duration::from_millis(10));
+    assert_eq!(histogram.percentile(1.0), Duration::from_millis(10));
+}
+
+#[test]
+fn test_compression_stats() {
+    let mut stats = CompressionStats::default();
+    stats.add(100, 50, Duration::from_micros(1), Duration::from_micros(1));
+    stats.add(127, 27, Duration::from_micros(2), Duration::from_micros(1));
+    let mut other = CompressionStats::default();
+    other.add(
+        1000,
+        100,
+        Duration::from_micros(3),
+        Duration::from_micros(2),
+    );
+    stats.merge(&other);
+
+    assert_eq!(stats.accounts, 3);
+    assert_eq!(stats.compression.total(), Duration::from_micros(6));
+    assert!((stats.compression_ratio() - 1227.0 / 177.0).abs() < 1e-9);
+    // 1227 bytes in 6µs
+    assert!((stats.compression_throughput().unwrap() - 0.2045).abs() < 1e-9);
+    assert_eq!(CompressionStats::default().decompression_throughput(), None);
+    assert_eq!(
+        stats.size_buckets().copied().collect::<Vec<_>>(),
+        vec![
+            SizeBucket {
+                min_data_len: 64,
+                max_data_len: 127,
+                accounts: 2,
+                uncompressed_bytes: 227,
+                compressed_bytes: 77,
+            },
+            SizeBucket {
+                min_data_len: 512,
+                max_data_len: 1023,
+                accounts: 1,
+                uncompressed_bytes: 1000,
+                compressed_bytes: 100,
+            },
+        ]
+    );
+}
@@ -0,0 +1,468 @@
+use {
+    crate::{
+        append_vec_iter, manifest::SnapshotManifest, AccountsDbFields, AppendVec,
+        AppendVecIterator, SerializableAccountStorageEntry, SnapshotExtractor, SnapshotResult,
+    },
+    log::info,
+    solana_sdk::clock::Slot,
+    std::{
+        cmp::Reverse,
+        collections::{BinaryHeap, HashMap},
+        fs::File,
+        io::{self, BufReader, BufWriter, Read, Write},
+        os::unix::fs::FileExt,
+        path::{Path, PathBuf},
+        time::Instant,
+    },
+    tempfile::TempDir,
+};
+
+/// A record that can be written to sorted runs on disk.
+trait RunRecord: Ord + Copy + 'static {
+    const SIZE: usize;
+
+    fn write_to(&self, buf: &mut [u8]);
+
+    fn read_from(buf: &[u8]) -> Self;
+}
+
+fn read_u64(buf: &[u8]) -> u64 {
+    u64::from_le_bytes(buf[..8].try_into().unwrap())
+}
+
+/// One stored version of an account, ordered by pubkey then age.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct AccountVersion {
+    pubkey: [u8; 32],
+    slot: Slot,
+    write_version: u64,
+    id: u64,
+    offset: u64,
+    is_tombstone: bool,
+}
+
+impl RunRecord for AccountVersion {
+    const SIZE: usize = 65;
+
+    fn write_to(&self, buf: &mut [u8]) {
+        buf[..32].copy_from_slice(&self.pubkey);
+        buf[32..40].copy_from_slice(&self.slot.to_le_bytes());
+        buf[40..48].copy_from_slice(&self.write_version.to_le_bytes());
+        buf[48..56].copy_from_slice(&self.id.to_le_bytes());
+        buf[56..64].copy_from_slice(&self.offset.to_le_bytes());
+        buf[64] = self.is_tombstone as u8;
+    }
+
+    fn read_from(buf: &[u8]) -> Self {
+        Self {
+            pubkey: buf[..32].try_into().unwrap(),
+            slot: read_u64(&buf[32..]),
+            write_version: read_u64(&buf[40..]),
+            id: read_u64(&buf[48..]),
+            offset: read_u64(&buf[56..]),
+            is_tombstone: buf[64] != 0,
+        }
+    }
+}
+
+/// Location of the newest version of an account.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct LatestAccount {
+    slot: Slot,
+    id: u64,
+    offset: u64,
+}
+
+impl RunRecord for LatestAccount {
+    const SIZE: usize = 24;
+
+    fn write_to(&self, buf: &mut [u8]) {
+        buf[..8].copy_from_slice(&self.slot.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.id.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.offset.to_le_bytes());
+    }
+
+    fn read_from(buf: &[u8]) -> Self {
+        Self {
+            slot: read_u64(buf),
+            id: read_u64(&buf[8..]),
+            offset: read_u64(&buf[16..]),
+        }
+    }
+}
+
+/// Runs merged at once, bounds the number of open files.
+const MAX_MERGED_RUNS: usize = 64;
+
+/// External sort: records are sorted in memory and spilled to disk as sorted runs once
+/// `max_records` is reached, the runs are k-way merged when reading back, in several passes
+/// when there are more than [`MAX_MERGED_RUNS`].
+struct SortedRuns<T: RunRecord> {
+    dir: PathBuf,
+    name: &'static str,
+    /// Allocated once so that it never grows past the memory budget.
+    buffer: Vec<T>,
+    max_records: usize,
+    runs: Vec<PathBuf>,
+    /// Number of run files created, names them.
+    created_runs: usize,
+}
+
+impl<T: RunRecord> SortedRuns<T> {
+    fn new(dir: &Path, name: &'static str, memory_budget: usize) -> Self {
+        let max_records = (memory_budget / std::mem::size_of::<T>()).max(1);
+        Self {
+            dir: dir.to_path_buf(),
+            name,
+            buffer: Vec::with_capacity(max_records),
+            max_records,
+            runs: Vec::new(),
+            created_runs: 0,
+        }
+    }
+
+    fn create_run(&mut self) -> io::Result<(PathBuf, BufWriter<File>)> {
+        let path = self
+            .dir
+            .join(format!("{}-{}.run", self.name, self.created_runs));
+        self.created_runs += 1;
+        let writer = BufWriter::new(File::create(&path)?);
+        Ok((path, writer))
+    }
+
+    fn push(&mut self, record: T) -> io::Result<()> {
+        self.buffer.push(record);
+        if self.buffer.len() >= self.max_records {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.buffer.sort_unstable();
+        let (path, mut writer) = self.create_run()?;
+        let mut buf = vec![0; T::SIZE];
+        for record in self.buffer.drain(..) {
+            record.write_to(&mut buf);
+            writer.write_all(&buf)?;
+        }
+        writer.flush()?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Returns all the records in order.
+    fn into_sorted_iter(mut self) -> io::Result<Box<dyn Iterator<Item = io::Result<T>>>> {
+        if self.runs.is_empty() {
+            self.buffer.sort_unstable();
+            return Ok(Box::new(self.buffer.into_iter().map(Ok)));
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        self.buffer = Vec::new();
+        while self.runs.len() > MAX_MERGED_RUNS {
+            let merged = self.runs.drain(..MAX_MERGED_RUNS).collect::<Vec<_>>();
+            let (path, mut writer) = self.create_run()?;
+            let mut buf = vec![0; T::SIZE];
+            for record in Self::merge(&merged)? {
+                record?.write_to(&mut buf);
+                writer.write_all(&buf)?;
+            }
+            writer.flush()?;
+            for run in merged {
+                std::fs::remove_file(run)?;
+            }
+            self.runs.push(path);
+        }
+        Self::merge(&self.runs)
+    }
+
+    /// K-way merge of sorted runs.
+    fn merge(runs: &[PathBuf]) -> io::Result<Box<dyn Iterator<Item = io::Result<T>>>> {
+        let mut readers = runs
+            .iter()
+            .map(|path| Ok(BufReader::new(File::open(path)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut heap = BinaryHeap::new();
+        for (index, reader) in readers.iter_mut().enumerate() {
+            if let Some(record) = Self::read_record(reader)? {
+                heap.push(Reverse((record, index)));
+            }
+        }
+        Ok(Box::new(std::iter::from_fn(move || {
+            let Reverse((record, index)) = heap.pop()?;
+            match Self::read_record(&mut readers[index]) {
+                Ok(Some(next)) => heap.push(Reverse((next, index))),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            Some(Ok(record))
+        })))
+    }
+
+    fn read_record(reader: &mut BufReader<File>) -> io::Result<Option<T>> {
+        let mut buf = vec![0; T::SIZE];
+        match reader.read_exact(&mut buf) {
+            Ok(()) => Ok(Some(T::read_from(&buf))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// On-disk index of the newest version of every account of a snapshot.
+///
+/// Versions are ordered by (slot, write version, AppendVec id, offset), accounts whose newest
+/// version has zero lamports are dropped. The index is built with external sorting, memory use
+/// is bounded by `memory_budget` plus one entry per AppendVec.
+pub struct LatestAccountsIndex {
+    _dir: TempDir,
+    latest_offsets: File,
+    /// (slot, AppendVec id) -> (index of the first offset, number of offsets)
+    append_vecs: HashMap<(Slot, u64), (u64, u64)>,
+}
+
+impl LatestAccountsIndex {
+    /// Reads all the AppendVecs of a snapshot, the extractor cannot be reused.
+    pub fn build<E: SnapshotExtractor>(
+        extractor: &mut E,
+        memory_budget: usize,
+    ) -> SnapshotResult<Self> {
+        let start = Instant::now();
+        let dir = tempfile::tempdir()?;
+
+        // the versions are still being merged while the latest accounts are sorted
+        let mut versions =
+            SortedRuns::<AccountVersion>::new(dir.path(), "versions", memory_budget / 2);
+        let mut account_versions = 0u64;
+        for append_vec in extractor.iter() {
+            let append_vec = append_vec?;
+            for handle in append_vec_iter(&append_vec) {
+                let Some(stored) = handle.access() else {
+                    continue;
+                };
+                versions.push(AccountVersion {
+                    pubkey: stored.meta.pubkey.to_bytes(),
+                    slot: append_vec.slot(),
+                    write_version: stored.meta.write_version_obsolete,
+                    id: append_vec.id(),
+                    offset: stored.offset as u64,
+                    is_tombstone: stored.account_meta.lamports == 0,
+                })?;
+                account_versions += 1;
+            }
+        }
+
+        let mut latest = SortedRuns::<LatestAccount>::new(dir.path(), "latest", memory_budget / 2);
+        let mut current: Option<AccountVersion> = None;
+        for version in versions.into_sorted_iter()? {
+            let version = version?;
+            match current {
+                Some(newest) if newest.pubkey != version.pubkey => {
+                    Self::push_latest(&mut latest, newest)?;
+                }
+                _ => {}
+            }
+            current = Some(version);
+        }
+        if let Some(newest) = current {
+            Self::push_latest(&mut latest, newest)?;
+        }
+
+        let latest_offsets_path = dir.path().join("latest_offsets");
+        let mut writer = BufWriter::new(File::create(&latest_offsets_path)?);
+        let mut append_vecs: HashMap<(Slot, u64), (u64, u64)> = HashMap::new();
+        let mut latest_accounts = 0u64;
+        for account in latest.into_sorted_iter()? {
+            let account = account?;
+            writer.write_all(&account.offset.to_le_bytes())?;
+            append_vecs
+                .entry((account.slot, account.id))
+                .or_insert((latest_accounts, 0))
+                .1 += 1;
+            latest_accounts += 1;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        info!(
+            "Indexed {latest_accounts} latest accounts out of {account_versions} stored versions in {:?}",
+            start.elapsed()
+        );
+        Ok(Self {
+            latest_offsets: File::open(latest_offsets_path)?,
+            _dir: dir,
+            append_vecs,
+        })
+    }
+
+    fn push_latest(
+        latest: &mut SortedRuns<LatestAccount>,
+        newest: AccountVersion,
+    ) -> io::Result<()> {
+        if newest.is_tombstone {
+            return Ok(());
+        }
+        latest.push(LatestAccount {
+            slot: newest.slot,
+            id: newest.id,
+            offset: newest.offset,
+        })
+    }
+
+    /// Sorted offsets of the newest accounts stored in the AppendVec `id` of `slot`.
+    pub fn latest_offsets(&self, slot: Slot, id: u64) -> SnapshotResult<Vec<usize>> {
+        let Some((first, count)) = self.append_vecs.get(&(slot, id)) else {
+            return Ok(Vec::new());
+        };
+        let mut buf = vec![0; *count as usize * 8];
+        self.latest_offsets.read_exact_at(&mut buf, first * 8)?;
+        Ok(buf
+            .chunks_exact(8)
+            .map(|offset| read_u64(offset) as usize)
+            .collect())
+    }
+
+    /// Copies the newest accounts of an AppendVec, returns `None` if it has none.
+    pub fn filter(&self, append_vec: AppendVec) -> SnapshotResult<Option<AppendVec>> {
+        let latest_offsets = self.latest_offsets(append_vec.slot(), append_vec.id())?;
+        Ok(append_vec.filter(|account| latest_offsets.binary_search(&account.offset).is_ok())?)
+    }
+}
+
+/// Yields only the newest version of every account, see `LatestAccountsIndex`.
+pub struct LatestAccountsExtractor<E: SnapshotExtractor> {
+    extractor: E,
+    index: LatestAccountsIndex,
+}
+
+impl<E: SnapshotExtractor> LatestAccountsExtractor<E> {
+    /// `index` must have been built from the same snapshot as `extractor`.
+    pub const fn new(extractor: E, index: LatestAccountsIndex) -> Self {
+        Self { extractor, index }
+    }
+}
+
+impl<E: SnapshotExtractor> SnapshotExtractor for LatestAccountsExtractor<E> {
+    fn iter(&mut self) -> AppendVecIterator<'_> {
+        let index = &self.index;
+        Box::new(self.extractor.iter().filter_map(move |append_vec| {
+            append_vec
+                .and_then(|append_vec| index.filter(append_vec))
+                .transpose()
+        }))
+    }
+
+    fn accounts_db_fields(&self) -> &AccountsDbFields<SerializableAccountStorageEntry> {
+        self.extractor.accounts_db_fields()
+    }
+
+    fn manifest(&self) -> &SnapshotManifest {
+        self.extractor.manifest()
+    }
+}
+
+#[test]
+fn test_sorted_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    // room for 10 records per run
+    let mut runs = SortedRuns::<LatestAccount>::new(dir.path(), "test", 240);
+    let mut expected = Vec::new();
+    for i in 0..95u64 {
+        let record = LatestAccount {
+            slot: (i * 7919) % 13,
+            id: i % 2,
+            offset: i,
+        };
+        runs.push(record).unwrap();
+        expected.push(record);
+    }
+    assert_eq!(runs.runs.len(), 9);
+    expected.sort();
+    let sorted = runs
+        .into_sorted_iter()
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert!(sorted == expected);
+
+    // one record per run, merged in several passes
+    let dir = tempfile::tempdir().unwrap();
+    let mut runs = SortedRuns::<LatestAccount>::new(dir.path(), "test", 24);
+    assert_eq!(runs.buffer.capacity(), 1);
+    let mut expected = (0..200u64)
+        .map(|i| LatestAccount {
+            slot: (i * 7919) % 13,
+            id: i % 3,
+            offset: i,
+        })
+        .collect::<Vec<_>>();
+    for record in &expected {
+        runs.push(*record).unwrap();
+    }
+    assert_eq!(runs.runs.len(), 200);
+    expected.sort();
+    let sorted = runs.into_sorted_iter().unwrap();
+    assert!(dir.path().read_dir().unwrap().count() <= MAX_MERGED_RUNS);
+    assert!(sorted.collect::<io::Result<Vec<_>>>().unwrap() == expected);
+}
+
+#[test]
+fn test_latest_accounts_index() {
+    use {
+        crate::{append_vec::test_append_vec, TestExtractor},
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    let owner = Pubkey::new_unique();
+    let [a, b, c, d] = [(); 4].map(|_| Pubkey::new_unique());
+    let test_extractor = || {
+        TestExtractor::new(
+            3,
+            vec![
+                test_append_vec(
+                    2,
+                    0,
+                    &[
+                        (a, 10, 1, owner, b"a2"),
+                        (b, 11, 0, owner, b""),
+                        (d, 13, 1, owner, b"d2"),
+                    ],
+                ),
+                test_append_vec(1, 0, &[(a, 1, 1, owner, b"a1"), (b, 2, 1, owner, b"b1")]),
+                test_append_vec(
+                    3,
+                    0,
+                    &[
+                        (c, 20, 1, owner, b"c3"),
+                        (c, 21, 1, owner, b"c3'"),
+                        (d, 3, 1, owner, b"d3"),
+                    ],
+                ),
+                // another storage of slot 3, its offsets are also used above
+                test_append_vec(3, 1, &[(a, 5, 1, owner, b"a3")]),
+            ],
+        )
+    };
+
+    // a tiny budget to go through the on-disk runs
+    let index = LatestAccountsIndex::build(&mut test_extractor(), 64).unwrap();
+    let mut extractor = LatestAccountsExtractor::new(test_extractor(), index);
+    let mut latest = Vec::new();
+    for append_vec in extractor.iter() {
+        let append_vec = append_vec.unwrap();
+        for handle in append_vec_iter(&append_vec) {
+            let stored = handle.access().unwrap();
+            latest.push((stored.meta.pubkey, stored.data.to_vec()));
+        }
+    }
+    latest.sort();
+    let mut expected = vec![
+        (a, b"a3".to_vec()),
+        (c, b"c3'".to_vec()),
+        (d, b"d3".to_vec()),
+    ];
+    expected.sort();
+    assert_eq!(latest, expected);
+}
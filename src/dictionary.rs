@@ -0,0 +1,432 @@
+use {
+    crate::{
+        append_vec::StoredAccountMeta,
+        codec::CodecKind,
+        dictionary_key::{AccountKeyScheme, DictionaryKey, KeyScheme, OwnerKeyScheme},
+        manifest::SnapshotManifest,
+        partial_pubkey_by_bits::PartialPubkeyByBits,
+        split::HoldoutSplit,
+    },
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    solana_sdk::{
+        clock::{Epoch, Slot},
+        hash::Hash,
+        pubkey::Pubkey,
+    },
+    std::{
+        collections::HashMap,
+        fs::File,
+        io::{BufReader, BufWriter, Read, Write},
+        path::Path,
+    },
+    thiserror::Error,
+};
+
+/// First bytes of a dictionary bundle file.
+pub const DICTIONARY_BUNDLE_MAGIC: [u8; 8] = *b"SOLDICTS";
+
+/// Format version written by [`DictionaryBundle::write`].
+pub const DICTIONARY_BUNDLE_VERSION: u32 = 1;
+
+const CHECKSUM_LEN: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum DictionaryError {
+    #[error("{0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Failed to deserialize: {0}")]
+    BincodeError(#[from] bincode::Error),
+    #[error("Unsupported dictionary bundle version {0}, expected {DICTIONARY_BUNDLE_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("Dictionary bundle checksum mismatch")]
+    ChecksumMismatch,
+    #[error("Truncated dictionary bundle")]
+    Truncated,
+}
+
+pub type DictionaryResult<T> = Result<T, DictionaryError>;
+
+/// Dictionaries written by older creators: a bare bincode map without header.
+pub type LegacyDictionaryMap = HashMap<PartialPubkeyByBits, Vec<u8>>;
+
+/// Snapshot the dictionaries were trained on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DictionarySource {
+    pub slot: Slot,
+    pub epoch: Epoch,
+    pub bank_hash: Hash,
+}
+
+impl From<&SnapshotManifest> for DictionarySource {
+    fn from(manifest: &SnapshotManifest) -> Self {
+        Self {
+            slot: manifest.slot,
+            epoch: manifest.epoch,
+            bank_hash: manifest.bank_hash,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrainingStats {
+    /// Accounts seen for the key while sampling.
+    pub accounts: u64,
+    /// Accounts used to train the dictionary.
+    pub samples: u64,
+    pub sample_bytes: u64,
+    /// Requested dictionary size, the trained dictionary may be smaller.
+    pub dictionary_size: u64,
+    /// Samples set aside to evaluate the dictionary, zero when it was not evaluated.
+    pub validation_bytes: u64,
+    /// Compressed size of the validation samples with the dictionary.
+    pub validation_compressed_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DictionaryEntry {
+    /// Identifies the dictionary within the bundle, stable across reads.
+    pub id: u32,
+    pub key: DictionaryKey,
+    pub dictionary: Vec<u8>,
+    pub stats: TrainingStats,
+}
+
+/// Self describing set of dictionaries.
+///
+/// On disk: magic, little endian format version, bincode payload and the sha256 of the payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DictionaryBundle {
+    /// Codec the dictionaries were trained for.
+    pub codec: CodecKind,
+    /// Schemes tried in order when resolving the dictionary of an account, most specific first.
+    pub key_schemes: Vec<KeyScheme>,
+    /// Unknown for legacy files.
+    pub source: Option<DictionarySource>,
+    /// Accounts of the test partition were not used for training.
+    pub split: Option<HoldoutSplit>,
+    entries: Vec<DictionaryEntry>,
+}
+
+impl DictionaryBundle {
+    pub const fn new(
+        codec: CodecKind,
+        key_schemes: Vec<KeyScheme>,
+        source: Option<DictionarySource>,
+    ) -> Self {
+        Self {
+            codec,
+            key_schemes,
+            source,
+            split: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds a dictionary and returns its id.
+    pub fn insert(&mut self, key: DictionaryKey, dictionary: Vec<u8>, stats: TrainingStats) -> u32 {
+        let id = self.entries.len() as u32 + 1;
+        self.entries.push(DictionaryEntry {
+            id,
+            key,
+            dictionary,
+            stats,
+        });
+        id
+    }
+
+    pub fn entries(&self) -> &[DictionaryEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &DictionaryKey) -> Option<&DictionaryEntry> {
+        self.entries.iter().find(|entry| entry.key == *key)
+    }
+
+    /// Dictionary shared by every account, used when no more specific one exists.
+    pub fn fallback(&self) -> Option<&DictionaryEntry> {
+        self.get(&DictionaryKey::ALL)
+    }
+
+    /// Total size of the dictionaries in bytes.
+    pub fn dictionaries_len(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry.dictionary.len())
+            .sum()
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> DictionaryResult<()> {
+        let payload = bincode::serialize(self)?;
+        writer.write_all(&DICTIONARY_BUNDLE_MAGIC)?;
+        writer.write_all(&DICTIONARY_BUNDLE_VERSION.to_le_bytes())?;
+        writer.write_all(&payload)?;
+        writer.write_all(&Sha256::digest(&payload))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a bundle, files without the magic are loaded as a [`LegacyDictionaryMap`].
+    pub fn read<R: Read>(mut reader: R) -> DictionaryResult<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let Some(data) = data.strip_prefix(&DICTIONARY_BUNDLE_MAGIC) else {
+            return Ok(Self::from_legacy(bincode::deserialize(&data)?));
+        };
+        if data.len() < 4 + CHECKSUM_LEN {
+            return Err(DictionaryError::Truncated);
+        }
+        let (version, data) = data.split_at(4);
+        let version = u32::from_le_bytes(version.try_into().unwrap());
+        if version != DICTIONARY_BUNDLE_VERSION {
+            return Err(DictionaryError::UnsupportedVersion(version));
+        }
+        let (payload, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+        if Sha256::digest(payload).as_slice() != checksum {
+            return Err(DictionaryError::ChecksumMismatch);
+        }
+        Ok(bincode::deserialize(payload)?)
+    }
+
+    pub fn save(&self, path: &Path) -> DictionaryResult<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: &Path) -> DictionaryResult<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Loads a bundle file along with the hex sha256 of its bytes, which identifies the
+    /// dictionaries used in reports.
+    pub fn load_with_checksum(path: &Path) -> DictionaryResult<(Self, String)> {
+        let data = std::fs::read(path)?;
+        Ok((
+            Self::read(data.as_slice())?,
+            format!("{:x}", Sha256::digest(&data)),
+        ))
+    }
+
+    /// Legacy maps were trained with zstd, ids are assigned in key order.
+    pub fn from_legacy(map: LegacyDictionaryMap) -> Self {
+        let nb_bits = map.keys().next().map(|key| key.nb_bits).unwrap_or_default();
+        let mut bundle = Self::new(
+            CodecKind::Zstd,
+            vec![KeyScheme::new(
+                OwnerKeyScheme::OwnerPrefixBits { nb_bits },
+                AccountKeyScheme::None,
+            )],
+            None,
+        );
+        let mut entries = map.into_iter().collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        for (key, dictionary) in entries {
+            bundle.insert(
+                DictionaryKey::owner(key),
+                dictionary,
+                TrainingStats::default(),
+            );
+        }
+        bundle
+    }
+}
+
+/// Finds the most specific dictionary of an account following the key schemes of a bundle.
+pub struct DictionaryResolver<T> {
+    key_schemes: Vec<KeyScheme>,
+    dictionaries: HashMap<DictionaryKey, T>,
+}
+
+/// Dictionary found for an account.
+pub struct Resolved<'a, T> {
+    /// Index of the key scheme the dictionary was found with.
+    pub level: usize,
+    pub key: DictionaryKey,
+    pub dictionary: &'a mut T,
+}
+
+impl<T> DictionaryResolver<T> {
+    pub fn new(key_schemes: Vec<KeyScheme>) -> Self {
+        Self {
+            key_schemes,
+            dictionaries: HashMap::new(),
+        }
+    }
+
+    /// Prepares the dictionaries of a bundle once, e.g. as codecs.
+    pub fn from_bundle<E>(
+        bundle: &DictionaryBundle,
+        mut prepare: impl FnMut(&DictionaryEntry) -> Result<T, E>,
+    ) -> Result<Self, E> {
+        let mut resolver = Self::new(bundle.key_schemes.clone());
+        for entry in bundle.entries() {
+            resolver.insert(entry.key, prepare(entry)?);
+        }
+        Ok(resolver)
+    }
+
+    pub fn insert(&mut self, key: DictionaryKey, dictionary: T) {
+        self.dictionaries.insert(key, dictionary);
+    }
+
+    pub fn key_schemes(&self) -> &[KeyScheme] {
+        &self.key_schemes
+    }
+
+    pub fn len(&self) -> usize {
+        self.dictionaries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dictionaries.is_empty()
+    }
+
+    pub fn resolve(&mut self, account: &StoredAccountMeta) -> Option<Resolved<'_, T>> {
+        self.resolve_for(&account.account_meta.owner, account.data)
+    }
+
+    /// Tries every key scheme in order, returns `None` when no level has a dictionary.
+    pub fn resolve_for(&mut self, owner: &Pubkey, data: &[u8]) -> Option<Resolved<'_, T>> {
+        let (level, key) = self
+            .key_schemes
+            .iter()
+            .map(|key_scheme| key_scheme.key_for(owner, data))
+            .enumerate()
+            .find(|(_, key)| self.dictionaries.contains_key(key))?;
+        Some(Resolved {
+            level,
+            key,
+            dictionary: self.dictionaries.get_mut(&key).unwrap(),
+        })
+    }
+}
+
+#[test]
+fn test_dictionary_bundle_roundtrip() {
+    let key_scheme = KeyScheme::new(
+        OwnerKeyScheme::OwnerPrefixBits { nb_bits: 12 },
+        AccountKeyScheme::None,
+    );
+    let mut bundle = DictionaryBundle::new(
+        CodecKind::Zstd,
+        vec![key_scheme],
+        Some(DictionarySource {
+            slot: 42,
+            epoch: 1,
+            bank_hash: Hash::new_unique(),
+        }),
+    );
+    for i in 0..3u8 {
+        let key = key_scheme.key_for(&Pubkey::new_from_array([i; 32]), &[]);
+        let stats = TrainingStats {
+            accounts: 10,
+            samples: 5,
+            sample_bytes: 500,
+            dictionary_size: 16,
+            validation_bytes: 100,
+            validation_compressed_bytes: 40,
+        };
+        assert_eq!(bundle.insert(key, vec![i; 16], stats), i as u32 + 1);
+    }
+
+    let mut file = Vec::new();
+    bundle.write(&mut file).unwrap();
+    assert_eq!(DictionaryBundle::read(file.as_slice()).unwrap(), bundle);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.bin");
+    bundle.save(&path).unwrap();
+    assert_eq!(DictionaryBundle::load(&path).unwrap(), bundle);
+    assert_eq!(
+        DictionaryBundle::load_with_checksum(&path).unwrap(),
+        (bundle.clone(), format!("{:x}", Sha256::digest(&file)))
+    );
+    assert_eq!(bundle.fallback(), None);
+
+    let top_owners = KeyScheme::new(
+        OwnerKeyScheme::TopOwners {
+            top_k: 1,
+            ranking: "bytes".parse().unwrap(),
+        },
+        AccountKeyScheme::Discriminator,
+    );
+    let owner = Pubkey::new_unique();
+    let key = top_owners.key_for(&owner, &[1; 8]);
+    assert_eq!(key.owner.bits, owner.to_bytes());
+    let mut top_owners_bundle =
+        DictionaryBundle::new(CodecKind::Zstd, vec![top_owners, KeyScheme::GLOBAL], None);
+    top_owners_bundle.split = Some(HoldoutSplit::new(10));
+    top_owners_bundle.insert(key, vec![1; 16], TrainingStats::default());
+    top_owners_bundle.insert(DictionaryKey::ALL, vec![2; 16], TrainingStats::default());
+    assert_eq!(top_owners_bundle.fallback().unwrap().id, 2);
+    let mut file = Vec::new();
+    top_owners_bundle.write(&mut file).unwrap();
+    assert_eq!(
+        DictionaryBundle::read(file.as_slice()).unwrap(),
+        top_owners_bundle
+    );
+
+    let last = file.len() - 1;
+    file[last] ^= 1;
+    assert!(matches!(
+        DictionaryBundle::read(file.as_slice()),
+        Err(DictionaryError::ChecksumMismatch)
+    ));
+    let version = DICTIONARY_BUNDLE_MAGIC.len();
+    file[version] = 2;
+    assert!(matches!(
+        DictionaryBundle::read(file.as_slice()),
+        Err(DictionaryError::UnsupportedVersion(2))
+    ));
+
+    let legacy = bundle
+        .entries()
+        .iter()
+        .map(|entry| (entry.key.owner, entry.dictionary.clone()))
+        .collect::<LegacyDictionaryMap>();
+    let loaded = DictionaryBundle::read(bincode::serialize(&legacy).unwrap().as_slice()).unwrap();
+    assert_eq!(loaded.key_schemes, vec![key_scheme]);
+    assert_eq!(loaded.source, None);
+    assert_eq!(loaded.entries().len(), 3);
+    for entry in bundle.entries() {
+        assert_eq!(loaded.get(&entry.key).unwrap().dictionary, entry.dictionary);
+    }
+}
+
+#[test]
+fn test_dictionary_resolver() {
+    let owner = Pubkey::new_unique();
+    let other_owner = Pubkey::new_unique();
+    let full_owner = |account| {
+        KeyScheme::new(
+            OwnerKeyScheme::OwnerPrefixBits {
+                nb_bits: crate::partial_pubkey_by_bits::MAX_NB_BITS,
+            },
+            account,
+        )
+    };
+    let key_schemes = vec![
+        full_owner(AccountKeyScheme::Discriminator),
+        full_owner(AccountKeyScheme::None),
+        KeyScheme::GLOBAL,
+    ];
+    let mut resolver = DictionaryResolver::new(key_schemes.clone());
+    resolver.insert(key_schemes[0].key_for(&owner, &[1; 8]), "discriminator");
+    resolver.insert(key_schemes[1].key_for(&owner, &[]), "owner");
+
+    let resolved = resolver.resolve_for(&owner, &[1; 16]).unwrap();
+    assert_eq!((resolved.level, *resolved.dictionary), (0, "discriminator"));
+    let resolved = resolver.resolve_for(&owner, &[2; 16]).unwrap();
+    assert_eq!((resolved.level, *resolved.dictionary), (1, "owner"));
+    assert!(resolver.resolve_for(&other_owner, &[1; 16]).is_none());
+
+    resolver.insert(DictionaryKey::ALL, "global");
+    let resolved = resolver.resolve_for(&other_owner, &[1; 16]).unwrap();
+    assert_eq!((resolved.level, resolved.key), (2, DictionaryKey::ALL));
+}
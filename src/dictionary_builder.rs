@@ -0,0 +1,647 @@
+use {
+    crate::{codec::CodecKind, sampling::Samples},
+    serde::{Deserialize, Serialize},
+    std::{ffi::CStr, fmt::Display, io, str::FromStr},
+    zstd::zstd_safe::zstd_sys::{
+        self, ZDICT_cover_params_t, ZDICT_fastCover_params_t, ZDICT_params_t,
+    },
+};
+
+/// lz4 only references the 64KiB before the data, larger dictionaries are truncated.
+pub const LZ4_WINDOW: usize = 64 * 1024;
+
+/// Length of the substrings counted by [`DictionaryBuilder::Segments`].
+const DMER_LEN: usize = 8;
+/// Length of the segments selected by [`DictionaryBuilder::Segments`].
+const SEGMENT_LEN: usize = 256;
+const HASH_LOG: u32 = 20;
+/// Size of the buffer `ZDICT_finalizeDictionary` writes the header and entropy tables in.
+const ZSTD_MAX_HEADER_LEN: usize = 256;
+
+/// How dictionaries are built from samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DictionaryBuilder {
+    /// zstd trainer, the dictionary has a zstd header and entropy tables.
+    Zstd,
+    /// Content section of a zstd dictionary, without header.
+    ZstdContent,
+    /// Segments containing the substrings shared by the most samples, without header.
+    Segments,
+    /// Segments finalized with a zstd header and entropy tables.
+    SegmentsZstd,
+}
+
+impl DictionaryBuilder {
+    pub const ALL: [DictionaryBuilder; 4] = [
+        DictionaryBuilder::Zstd,
+        DictionaryBuilder::ZstdContent,
+        DictionaryBuilder::Segments,
+        DictionaryBuilder::SegmentsZstd,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            DictionaryBuilder::Zstd => "zstd",
+            DictionaryBuilder::ZstdContent => "zstd-content",
+            DictionaryBuilder::Segments => "segments",
+            DictionaryBuilder::SegmentsZstd => "segments-zstd",
+        }
+    }
+
+    /// zstd dictionaries for zstd, raw content for lz4 which cannot use the entropy tables.
+    pub const fn default_for(codec: CodecKind) -> Self {
+        match codec {
+            CodecKind::Zstd => DictionaryBuilder::Zstd,
+            CodecKind::None | CodecKind::Lz4 | CodecKind::Lz4Flex => DictionaryBuilder::ZstdContent,
+        }
+    }
+
+    /// Builds a dictionary of at most `dictionary_size` bytes, raw dictionaries are also capped
+    /// to the lz4 window. `params` are used by the zstd trainer and to finalize dictionaries.
+    pub fn build(
+        self,
+        samples: &Samples,
+        dictionary_size: usize,
+        params: &ZstdTrainingParams,
+    ) -> io::Result<Vec<u8>> {
+        let raw_size = dictionary_size.min(LZ4_WINDOW);
+        match self {
+            DictionaryBuilder::Zstd => params.train(samples, dictionary_size),
+            DictionaryBuilder::ZstdContent => {
+                // leave room for the header so that the content fills raw_size
+                let dictionary = params.train(samples, raw_size + ZSTD_MAX_HEADER_LEN)?;
+                let content = zstd_content(&dictionary)?;
+                // the most useful content is at the end, closest to the data
+                Ok(content[content.len().saturating_sub(raw_size)..].to_vec())
+            }
+            DictionaryBuilder::Segments => Ok(build_segments(samples, raw_size)),
+            DictionaryBuilder::SegmentsZstd => {
+                let content = build_segments(samples, dictionary_size);
+                params.finalize(&content, samples, dictionary_size)
+            }
+        }
+    }
+}
+
+impl Display for DictionaryBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DictionaryBuilder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DictionaryBuilder::ALL
+            .into_iter()
+            .find(|builder| builder.name() == s)
+            .ok_or_else(|| format!("Unknown dictionary builder {s}"))
+    }
+}
+
+/// zstd dictionary training algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrainingAlgorithm {
+    Cover,
+    FastCover,
+}
+
+impl TrainingAlgorithm {
+    pub const ALL: [TrainingAlgorithm; 2] =
+        [TrainingAlgorithm::Cover, TrainingAlgorithm::FastCover];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            TrainingAlgorithm::Cover => "cover",
+            TrainingAlgorithm::FastCover => "fastcover",
+        }
+    }
+}
+
+/// Parameters of the zstd dictionary trainer.
+///
+/// Written as `<algorithm>[:<name>=<value>,...]`, e.g. `cover:k=1024,d=8` or
+/// `fastcover:d=8,steps=4,optimize`, with the names and defaults of the zstd cli `--train-cover`
+/// and `--train-fastcover` options: parameters that are not given take the zstd cli defaults,
+/// and `k` and `d` are optimized unless both are given.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ZstdTrainingParams {
+    pub algorithm: TrainingAlgorithm,
+    /// Segment size.
+    pub k: u32,
+    /// Dmer size.
+    pub d: u32,
+    /// Log of the frequency table size, fastcover only.
+    pub f: u32,
+    /// Number of k values tried when optimizing.
+    pub steps: u32,
+    /// Fraction of the samples used for training when optimizing, the others test the
+    /// dictionaries, ignored unless `optimize` is set.
+    pub split: f64,
+    /// Speeds up fastcover at the cost of accuracy, from 1 to 10.
+    pub accel: u32,
+    /// Compression level the entropy tables are built for.
+    pub level: i32,
+    /// Dictionary id, derived from the content when zero.
+    pub dict_id: u32,
+    pub threads: u32,
+    /// Tries `steps` values of `k`, and both 6 and 8 for `d` when zero, `k` and `d` must be
+    /// positive otherwise.
+    pub optimize: bool,
+}
+
+impl Default for ZstdTrainingParams {
+    /// Parameters of `ZDICT_trainFromBuffer`.
+    fn default() -> Self {
+        Self {
+            algorithm: TrainingAlgorithm::FastCover,
+            k: 0,
+            d: 8,
+            f: 20,
+            steps: 4,
+            split: 0.75,
+            accel: 1,
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            dict_id: 0,
+            threads: 0,
+            optimize: true,
+        }
+    }
+}
+
+impl ZstdTrainingParams {
+    const fn dictionary_params(&self) -> ZDICT_params_t {
+        ZDICT_params_t {
+            compressionLevel: self.level,
+            notificationLevel: 0,
+            dictID: self.dict_id,
+        }
+    }
+
+    /// Trains a zstd dictionary of at most `dictionary_size` bytes.
+    pub fn train(&self, samples: &Samples, dictionary_size: usize) -> io::Result<Vec<u8>> {
+        let mut dictionary = vec![0u8; dictionary_size];
+        let dictionary_ptr = dictionary.as_mut_ptr().cast();
+        let samples_ptr = samples.samples.as_ptr().cast();
+        let nb_samples = samples.sizes.len() as u32;
+        let threads = self.threads.max(1);
+        // SAFETY: the buffers are valid for the lengths given and the params are passed by value
+        // or point to a local.
+        let len = unsafe {
+            match self.algorithm {
+                TrainingAlgorithm::Cover => {
+                    let mut params = ZDICT_cover_params_t {
+                        k: self.k,
+                        d: self.d,
+                        steps: self.steps,
+                        nbThreads: threads,
+                        splitPoint: self.split,
+                        shrinkDict: 0,
+                        shrinkDictMaxRegression: 0,
+                        zParams: self.dictionary_params(),
+                    };
+                    if self.optimize {
+                        zstd_sys::ZDICT_optimizeTrainFromBuffer_cover(
+                            dictionary_ptr,
+                            dictionary_size,
+                            samples_ptr,
+                            samples.sizes.as_ptr(),
+                            nb_samples,
+                            &mut params,
+                        )
+                    } else {
+                        zstd_sys::ZDICT_trainFromBuffer_cover(
+                            dictionary_ptr,
+                            dictionary_size,
+                            samples_ptr,
+                            samples.sizes.as_ptr(),
+                            nb_samples,
+                            params,
+                        )
+                    }
+                }
+                TrainingAlgorithm::FastCover => {
+                    let mut params = ZDICT_fastCover_params_t {
+                        k: self.k,
+                        d: self.d,
+                        f: self.f,
+                        steps: self.steps,
+                        nbThreads: threads,
+                        splitPoint: self.split,
+                        accel: self.accel,
+                        shrinkDict: 0,
+                        shrinkDictMaxRegression: 0,
+                        zParams: self.dictionary_params(),
+                    };
+                    if self.optimize {
+                        zstd_sys::ZDICT_optimizeTrainFromBuffer_fastCover(
+                            dictionary_ptr,
+                            dictionary_size,
+                            samples_ptr,
+                            samples.sizes.as_ptr(),
+                            nb_samples,
+                            &mut params,
+                        )
+                    } else {
+                        zstd_sys::ZDICT_trainFromBuffer_fastCover(
+                            dictionary_ptr,
+                            dictionary_size,
+                            samples_ptr,
+                            samples.sizes.as_ptr(),
+                            nb_samples,
+                            params,
+                        )
+                    }
+                }
+            }
+        };
+        dictionary.truncate(zdict_result(len)?);
+        Ok(dictionary)
+    }
+
+    /// Adds a zstd header and entropy tables computed on `samples` to raw `content`, the start
+    /// of the content is dropped to leave room for the largest header in `dictionary_size`
+    /// bytes.
+    pub fn finalize(
+        &self,
+        content: &[u8],
+        samples: &Samples,
+        dictionary_size: usize,
+    ) -> io::Result<Vec<u8>> {
+        // zstd drops the end of the content that does not fit, the most useful part
+        let content = &content[content
+            .len()
+            .saturating_sub(dictionary_size.saturating_sub(ZSTD_MAX_HEADER_LEN))..];
+        let mut dictionary = vec![0u8; dictionary_size];
+        // SAFETY: the buffers are valid for the lengths given.
+        let len = unsafe {
+            zstd_sys::ZDICT_finalizeDictionary(
+                dictionary.as_mut_ptr().cast(),
+                dictionary_size,
+                content.as_ptr().cast(),
+                content.len(),
+                samples.samples.as_ptr().cast(),
+                samples.sizes.as_ptr(),
+                samples.sizes.len() as u32,
+                self.dictionary_params(),
+            )
+        };
+        dictionary.truncate(zdict_result(len)?);
+        Ok(dictionary)
+    }
+}
+
+fn zdict_result(code: usize) -> io::Result<usize> {
+    // SAFETY: ZDICT_isError only inspects the value and error names are static strings.
+    unsafe {
+        if zstd_sys::ZDICT_isError(code) != 0 {
+            let name = CStr::from_ptr(zstd_sys::ZDICT_getErrorName(code));
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                name.to_string_lossy().into_owned(),
+            ));
+        }
+    }
+    Ok(code)
+}
+
+impl Display for TrainingAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Display for ZstdTrainingParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:k={},d={}", self.algorithm, self.k, self.d)?;
+        if self.algorithm == TrainingAlgorithm::FastCover {
+            write!(f, ",f={}", self.f)?;
+        }
+        write!(f, ",steps={},split={}", self.steps, self.split)?;
+        if self.algorithm == TrainingAlgorithm::FastCover {
+            write!(f, ",accel={}", self.accel)?;
+        }
+        write!(
+            f,
+            ",level={},dict-id={},threads={}",
+            self.level, self.dict_id, self.threads
+        )?;
+        if self.optimize {
+            f.write_str(",optimize")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TrainingAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TrainingAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == s)
+            .ok_or_else(|| format!("Unknown training algorithm {s}"))
+    }
+}
+
+fn parse_param<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {value} of training parameter {name}"))
+}
+
+impl FromStr for ZstdTrainingParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, params) = s.split_once(':').unwrap_or((s, ""));
+        let algorithm = algorithm.parse::<TrainingAlgorithm>()?;
+        let fastcover = algorithm == TrainingAlgorithm::FastCover;
+        // defaults of the zstd cli `--train-cover` and `--train-fastcover` options
+        let mut training = Self {
+            algorithm,
+            f: if fastcover { 20 } else { 0 },
+            split: if fastcover { 0.75 } else { 1.0 },
+            accel: u32::from(fastcover),
+            optimize: false,
+            ..Self::default()
+        };
+        for param in params.split(',').filter(|param| !param.is_empty()) {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            match name {
+                "k" => training.k = parse_param(name, value)?,
+                "d" => training.d = parse_param(name, value)?,
+                "f" if fastcover => training.f = parse_param(name, value)?,
+                "steps" => training.steps = parse_param(name, value)?,
+                "split" => training.split = parse_param(name, value)?,
+                "accel" if fastcover => training.accel = parse_param(name, value)?,
+                "level" => training.level = parse_param(name, value)?,
+                "dict-id" => training.dict_id = parse_param(name, value)?,
+                "threads" => training.threads = parse_param(name, value)?,
+                "optimize" if value.is_empty() => training.optimize = true,
+                _ => return Err(format!("Unknown {algorithm} training parameter {param}")),
+            }
+        }
+        if fastcover && (training.f == 0 || !(1..=10).contains(&training.accel)) {
+            return Err(format!(
+                "Training parameter f must be positive and accel within 1 and 10 in {s}"
+            ));
+        }
+        // zstd only trains without optimizing when both k and d are given, as its cli does
+        training.optimize |= training.k == 0 || training.d == 0;
+        Ok(training)
+    }
+}
+
+/// Content of a zstd dictionary without its header and entropy tables, raw content dictionaries
+/// are returned as is.
+pub fn zstd_content(dictionary: &[u8]) -> io::Result<&[u8]> {
+    // SAFETY: the buffer is only read within its length.
+    let header_len =
+        unsafe { zstd_sys::ZDICT_getDictHeaderSize(dictionary.as_ptr().cast(), dictionary.len()) };
+    // SAFETY: ZDICT_isError only inspects the value.
+    if unsafe { zstd_sys::ZDICT_isError(header_len) } != 0 {
+        return if dictionary.starts_with(&zstd::zstd_safe::MAGIC_DICTIONARY.to_le_bytes()) {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "corrupted zstd dictionary header",
+            ))
+        } else {
+            Ok(dictionary)
+        };
+    }
+    Ok(&dictionary[header_len..])
+}
+
+fn dmer_hash(dmer: &[u8]) -> usize {
+    let dmer = u64::from_le_bytes(dmer.try_into().unwrap());
+    (dmer.wrapping_mul(0xcf1b_bcdc_b7a5_6463) >> (u64::BITS - HASH_LOG)) as usize
+}
+
+/// Simplified COVER: the samples are split in about one epoch per segment, the segment of every
+/// epoch whose distinct substrings appear in the most samples is selected, and the substrings it
+/// covers no longer count for the next epochs.
+fn build_segments(samples: &Samples, dictionary_size: usize) -> Vec<u8> {
+    let data = &samples.samples;
+    if data.len() < SEGMENT_LEN || dictionary_size == 0 {
+        return Vec::new();
+    }
+    // number of samples containing each substring, by hash
+    let mut frequencies = vec![0u32; 1 << HASH_LOG];
+    let mut last_sample = vec![0u32; 1 << HASH_LOG];
+    let mut offset = 0;
+    for (sample_index, &size) in samples.sizes.iter().enumerate() {
+        let sample = &data[offset..offset + size];
+        for dmer in sample.windows(DMER_LEN) {
+            let hash = dmer_hash(dmer);
+            if last_sample[hash] != sample_index as u32 + 1 {
+                last_sample[hash] = sample_index as u32 + 1;
+                frequencies[hash] += 1;
+            }
+        }
+        offset += size;
+    }
+    drop(last_sample);
+
+    let nb_segments = dictionary_size.div_ceil(SEGMENT_LEN);
+    let epoch_len = (data.len() / nb_segments).max(SEGMENT_LEN);
+    let dmers_per_segment = SEGMENT_LEN - DMER_LEN + 1;
+    // occurrences of each substring in the current window
+    let mut in_window = vec![0u16; 1 << HASH_LOG];
+    let mut segments = Vec::new();
+    // epochs of whole samples, samples larger than an epoch are split in epochs of their own
+    let mut epochs = Vec::new();
+    let (mut start, mut end) = (0, 0);
+    for &size in &samples.sizes {
+        if end > start && end - start + size > epoch_len {
+            epochs.push(start..end);
+            start = end;
+        }
+        end += size;
+        if size > epoch_len {
+            epochs.extend(
+                (start..end)
+                    .step_by(epoch_len)
+                    .map(|epoch_start| epoch_start..(epoch_start + epoch_len).min(end)),
+            );
+            start = end;
+        }
+    }
+    epochs.push(start..end);
+    for epoch in epochs {
+        let epoch = &data[epoch];
+        if epoch.len() < SEGMENT_LEN {
+            continue;
+        }
+        let hashes = epoch.windows(DMER_LEN).map(dmer_hash).collect::<Vec<_>>();
+        let mut score = 0u64;
+        let mut best = (0u64, 0usize);
+        for (end, &hash) in hashes.iter().enumerate() {
+            if in_window[hash] == 0 {
+                score += frequencies[hash] as u64;
+            }
+            in_window[hash] += 1;
+            if end >= dmers_per_segment {
+                let hash = hashes[end - dmers_per_segment];
+                in_window[hash] -= 1;
+                if in_window[hash] == 0 {
+                    score -= frequencies[hash] as u64;
+                }
+            }
+            if end + 1 >= dmers_per_segment && score > best.0 {
+                best = (score, end + 1 - dmers_per_segment);
+            }
+        }
+        for &hash in &hashes[hashes.len().saturating_sub(dmers_per_segment)..] {
+            in_window[hash] = 0;
+        }
+        let (score, start) = best;
+        if score == 0 {
+            continue;
+        }
+        for &hash in &hashes[start..start + dmers_per_segment] {
+            frequencies[hash] = 0;
+        }
+        segments.push((score, &epoch[start..start + SEGMENT_LEN]));
+    }
+
+    // highest scores last, closest to the data
+    segments.sort_by_key(|(score, _)| *score);
+    let dictionary = segments
+        .into_iter()
+        .flat_map(|(_, segment)| segment)
+        .copied()
+        .collect::<Vec<_>>();
+    dictionary[dictionary.len().saturating_sub(dictionary_size)..].to_vec()
+}
+
+#[test]
+fn test_dictionary_builders() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let layouts = (0..4)
+        .map(|_| (0..96).map(|_| rng.gen()).collect::<Vec<u8>>())
+        .collect::<Vec<_>>();
+    let mut samples = Samples::default();
+    for i in 0..2000 {
+        let mut data = layouts[i % layouts.len()].clone();
+        data.extend((0..32).map(|_| rng.gen::<u8>()));
+        samples.add(&data);
+    }
+    let compressed_len = |dictionary: Option<&[u8]>| {
+        let mut codec = CodecKind::Lz4Flex.create(None, dictionary).unwrap();
+        let mut output = vec![0; codec.max_compressed_len(1024)];
+        samples
+            .iter()
+            .map(|sample| codec.compress_into(sample, &mut output).unwrap())
+            .sum::<usize>()
+    };
+    let no_dictionary = compressed_len(None);
+    let params = ZstdTrainingParams::default();
+
+    let zstd = DictionaryBuilder::Zstd
+        .build(&samples, 4096, &params)
+        .unwrap();
+    assert!(zstd.len() <= 4096);
+    let content = zstd_content(&zstd).unwrap();
+    assert!(content.len() < zstd.len());
+    assert!(zstd.ends_with(content));
+    assert_eq!(zstd_content(content).unwrap(), content);
+
+    for builder in [DictionaryBuilder::ZstdContent, DictionaryBuilder::Segments] {
+        let dictionary = builder.build(&samples, 4096, &params).unwrap();
+        assert!(dictionary.len() <= 4096, "{builder}");
+        assert!(
+            layouts.iter().all(|layout| dictionary
+                .windows(64)
+                .any(|window| layout.windows(64).any(|l| l == window))),
+            "{builder}"
+        );
+        assert!(
+            compressed_len(Some(&dictionary)) < no_dictionary / 2,
+            "{builder}"
+        );
+        assert_eq!(
+            builder.name().parse::<DictionaryBuilder>().unwrap(),
+            builder
+        );
+    }
+    assert!(
+        DictionaryBuilder::Segments
+            .build(&samples, 1 << 20, &params)
+            .unwrap()
+            .len()
+            <= LZ4_WINDOW
+    );
+}
+
+#[test]
+fn test_zstd_training_params() {
+    let params = ZstdTrainingParams::default();
+    assert_eq!(params.to_string().parse::<ZstdTrainingParams>(), Ok(params));
+    assert_eq!("fastcover".parse::<ZstdTrainingParams>(), Ok(params));
+    let cover = "cover:k=64,d=8,split=0.5"
+        .parse::<ZstdTrainingParams>()
+        .unwrap();
+    assert_eq!(cover.algorithm, TrainingAlgorithm::Cover);
+    assert_eq!((cover.k, cover.d, cover.split), (64, 8, 0.5));
+    assert!(!cover.optimize);
+    assert_eq!(cover.to_string().parse::<ZstdTrainingParams>(), Ok(cover));
+    assert!("cover:accel=2".parse::<ZstdTrainingParams>().is_err());
+    assert!("fastcover:k=x".parse::<ZstdTrainingParams>().is_err());
+    assert!("lz4".parse::<ZstdTrainingParams>().is_err());
+    assert!("fastcover:f=0".parse::<ZstdTrainingParams>().is_err());
+    assert!("fastcover:accel=11".parse::<ZstdTrainingParams>().is_err());
+
+    let mut samples = Samples::default();
+    for i in 0..1000u32 {
+        let mut data = format!("owner {} layout ", i % 5).repeat(6).into_bytes();
+        data.extend_from_slice(&i.to_le_bytes());
+        samples.add(&data);
+    }
+    let cover = ZstdTrainingParams {
+        dict_id: 42,
+        ..cover
+    };
+    let dictionary = cover.train(&samples, 2048).unwrap();
+    assert!(dictionary.len() <= 2048);
+    assert_eq!(dictionary, cover.train(&samples, 2048).unwrap());
+    assert_eq!(zstd::zstd_safe::get_dict_id(&dictionary).unwrap().get(), 42);
+    let optimized = "fastcover:d=8,steps=2,optimize"
+        .parse::<ZstdTrainingParams>()
+        .unwrap();
+    assert!(optimized.train(&samples, 2048).unwrap().len() <= 2048);
+    // without k, zstd only accepts the parameters when optimizing
+    for params in ["cover", "fastcover:d=8", "cover:k=64,d=0"] {
+        let params = params.parse::<ZstdTrainingParams>().unwrap();
+        assert!(params.optimize, "{params}");
+        assert!(params.train(&samples, 2048).unwrap().len() <= 2048);
+    }
+    assert!("cover:k=4,d=8"
+        .parse::<ZstdTrainingParams>()
+        .unwrap()
+        .train(&samples, 2048)
+        .is_err());
+
+    let content = zstd_content(&dictionary).unwrap();
+    let finalized = cover.finalize(content, &samples, 2048).unwrap();
+    // the start of the content is dropped when the new header is larger
+    assert!(finalized.len() <= 2048);
+    let finalized_content = zstd_content(&finalized).unwrap();
+    assert!(!finalized_content.is_empty());
+    assert!(content.ends_with(finalized_content));
+    let long_content = samples.samples[..8192].to_vec();
+    let finalized = cover.finalize(&long_content, &samples, 2048).unwrap();
+    assert!(finalized.len() <= 2048);
+    let finalized_content = zstd_content(&finalized).unwrap();
+    assert_eq!(finalized_content.len(), 2048 - ZSTD_MAX_HEADER_LEN);
+    assert!(long_content.ends_with(finalized_content));
+    let dictionary = DictionaryBuilder::SegmentsZstd
+        .build(&samples, 1024, &cover)
+        .unwrap();
+    assert!(dictionary.len() <= 1024);
+    assert!(zstd_content(&dictionary).unwrap().len() < dictionary.len());
+}
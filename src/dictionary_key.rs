@@ -0,0 +1,327 @@
+use {
+    crate::{
+        append_vec::StoredAccountMeta,
+        partial_pubkey_by_bits::{PartialPubkeyByBits, MAX_NB_BITS},
+    },
+    serde::{Deserialize, Serialize},
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        cmp::Reverse,
+        collections::{HashMap, HashSet},
+        fmt::Display,
+        str::FromStr,
+    },
+};
+
+/// Length of the Anchor account discriminator.
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// Key of the dictionary used to compress an account.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct DictionaryKey {
+    pub owner: PartialPubkeyByBits,
+    pub account: AccountKey,
+}
+
+impl DictionaryKey {
+    /// Key of the dictionary shared by every account.
+    pub const ALL: Self = Self {
+        owner: PartialPubkeyByBits::ALL,
+        account: AccountKey::Any,
+    };
+
+    pub const fn owner(owner: PartialPubkeyByBits) -> Self {
+        Self {
+            owner,
+            account: AccountKey::Any,
+        }
+    }
+}
+
+impl Display for DictionaryKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.account {
+            AccountKey::Any => write!(f, "{}", self.owner),
+            account => write!(f, "{} {account}", self.owner),
+        }
+    }
+}
+
+/// Distinguishes accounts of the same owner.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum AccountKey {
+    Any,
+    /// First bytes of the data, zero padded when the data is shorter.
+    Discriminator([u8; DISCRIMINATOR_LEN]),
+    DataLen(u64),
+    /// Accounts with `data_len` in `[2^(bucket - 1), 2^bucket)`, bucket 0 holds empty accounts.
+    DataLenBucket(u8),
+}
+
+impl Display for AccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountKey::Any => f.write_str("any"),
+            AccountKey::Discriminator(discriminator) => {
+                f.write_str("discriminator=")?;
+                discriminator
+                    .iter()
+                    .try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+            AccountKey::DataLen(len) => write!(f, "data-len={len}"),
+            AccountKey::DataLenBucket(bucket) => write!(f, "data-len-bucket={bucket}"),
+        }
+    }
+}
+
+/// How accounts are mapped to the key of their dictionary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyScheme {
+    pub owner: OwnerKeyScheme,
+    pub account: AccountKeyScheme,
+}
+
+impl KeyScheme {
+    /// Maps every account to [`DictionaryKey::ALL`].
+    pub const GLOBAL: Self = Self {
+        owner: OwnerKeyScheme::OwnerPrefixBits { nb_bits: 0 },
+        account: AccountKeyScheme::None,
+    };
+
+    pub const fn new(owner: OwnerKeyScheme, account: AccountKeyScheme) -> Self {
+        Self { owner, account }
+    }
+
+    pub fn key(&self, account: &StoredAccountMeta) -> DictionaryKey {
+        self.key_for(&account.account_meta.owner, account.data)
+    }
+
+    pub fn key_for(&self, owner: &Pubkey, data: &[u8]) -> DictionaryKey {
+        DictionaryKey {
+            owner: self.owner.key(owner),
+            account: self.account.key(data),
+        }
+    }
+}
+
+/// How the owner part of the key is derived.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OwnerKeyScheme {
+    /// First `nb_bits` bits of the owner.
+    OwnerPrefixBits { nb_bits: u16 },
+    /// Full owner of the `top_k` owners with the highest `ranking`, other accounts use the
+    /// fallback dictionary.
+    TopOwners { top_k: u32, ranking: OwnerRanking },
+}
+
+impl OwnerKeyScheme {
+    pub fn key(&self, owner: &Pubkey) -> PartialPubkeyByBits {
+        match self {
+            OwnerKeyScheme::OwnerPrefixBits { nb_bits } => {
+                PartialPubkeyByBits::new(*owner, *nb_bits)
+            }
+            OwnerKeyScheme::TopOwners { .. } => PartialPubkeyByBits::new(*owner, MAX_NB_BITS),
+        }
+    }
+}
+
+/// How the account part of the key is derived from the account data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccountKeyScheme {
+    None,
+    Discriminator,
+    DataLen,
+    DataLenBucket,
+}
+
+impl AccountKeyScheme {
+    pub const ALL: [AccountKeyScheme; 4] = [
+        AccountKeyScheme::None,
+        AccountKeyScheme::Discriminator,
+        AccountKeyScheme::DataLen,
+        AccountKeyScheme::DataLenBucket,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            AccountKeyScheme::None => "none",
+            AccountKeyScheme::Discriminator => "discriminator",
+            AccountKeyScheme::DataLen => "data-len",
+            AccountKeyScheme::DataLenBucket => "data-len-bucket",
+        }
+    }
+
+    pub fn key(self, data: &[u8]) -> AccountKey {
+        match self {
+            AccountKeyScheme::None => AccountKey::Any,
+            AccountKeyScheme::Discriminator => {
+                let mut discriminator = [0; DISCRIMINATOR_LEN];
+                let len = data.len().min(DISCRIMINATOR_LEN);
+                discriminator[..len].copy_from_slice(&data[..len]);
+                AccountKey::Discriminator(discriminator)
+            }
+            AccountKeyScheme::DataLen => AccountKey::DataLen(data.len() as u64),
+            AccountKeyScheme::DataLenBucket => {
+                AccountKey::DataLenBucket((usize::BITS - data.len().leading_zeros()) as u8)
+            }
+        }
+    }
+}
+
+impl Display for AccountKeyScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AccountKeyScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AccountKeyScheme::ALL
+            .into_iter()
+            .find(|scheme| scheme.name() == s)
+            .ok_or_else(|| format!("Unknown account key scheme {s}"))
+    }
+}
+
+/// How owners are ordered to select the ones getting their own dictionary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OwnerRanking {
+    Accounts,
+    Bytes,
+}
+
+impl OwnerRanking {
+    pub const ALL: [OwnerRanking; 2] = [OwnerRanking::Accounts, OwnerRanking::Bytes];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            OwnerRanking::Accounts => "accounts",
+            OwnerRanking::Bytes => "bytes",
+        }
+    }
+
+    pub const fn score(self, accounts: u64, bytes: u64) -> u64 {
+        match self {
+            OwnerRanking::Accounts => accounts,
+            OwnerRanking::Bytes => bytes,
+        }
+    }
+
+    /// The `top_k` owners with the highest score given their `(accounts, bytes)` totals, ties
+    /// are broken by owner so that the selection is reproducible.
+    pub fn top_owners(
+        self,
+        owner_totals: &HashMap<PartialPubkeyByBits, (u64, u64)>,
+        top_k: usize,
+    ) -> HashSet<PartialPubkeyByBits> {
+        let mut owners = owner_totals.iter().collect::<Vec<_>>();
+        owners.sort_unstable_by_key(|(owner, (accounts, bytes))| {
+            (Reverse(self.score(*accounts, *bytes)), **owner)
+        });
+        owners
+            .into_iter()
+            .take(top_k)
+            .map(|(owner, _)| *owner)
+            .collect()
+    }
+}
+
+impl Display for OwnerRanking {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OwnerRanking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OwnerRanking::ALL
+            .into_iter()
+            .find(|ranking| ranking.name() == s)
+            .ok_or_else(|| format!("Unknown owner ranking {s}"))
+    }
+}
+
+#[test]
+fn test_account_keys() {
+    let owner = Pubkey::new_unique();
+    let data = (1..=16).collect::<Vec<u8>>();
+    let key = |account| KeyScheme::new(OwnerKeyScheme::OwnerPrefixBits { nb_bits: 8 }, account);
+
+    let discriminator = key(AccountKeyScheme::Discriminator).key_for(&owner, &data);
+    assert_eq!(discriminator.owner, PartialPubkeyByBits::new(owner, 8));
+    assert_eq!(
+        discriminator.account,
+        AccountKey::Discriminator([1, 2, 3, 4, 5, 6, 7, 8])
+    );
+    assert_eq!(
+        key(AccountKeyScheme::Discriminator)
+            .key_for(&owner, &data[..2])
+            .account,
+        AccountKey::Discriminator([1, 2, 0, 0, 0, 0, 0, 0])
+    );
+    assert_eq!(
+        key(AccountKeyScheme::DataLen)
+            .key_for(&owner, &data)
+            .account,
+        AccountKey::DataLen(16)
+    );
+    for (len, bucket) in [(0, 0), (1, 1), (2, 2), (3, 2), (15, 4), (16, 5)] {
+        assert_eq!(
+            key(AccountKeyScheme::DataLenBucket)
+                .key_for(&owner, &data[..len])
+                .account,
+            AccountKey::DataLenBucket(bucket)
+        );
+    }
+    assert_eq!(
+        key(AccountKeyScheme::None).key_for(&owner, &data),
+        DictionaryKey::owner(PartialPubkeyByBits::new(owner, 8))
+    );
+    for scheme in AccountKeyScheme::ALL {
+        assert_eq!(scheme.name().parse::<AccountKeyScheme>().unwrap(), scheme);
+    }
+    assert_eq!(
+        discriminator.to_string(),
+        format!(
+            "{}/8 discriminator=0102030405060708",
+            Pubkey::new_from_array(PartialPubkeyByBits::new(owner, 8).bits)
+        )
+    );
+    assert_eq!(
+        DictionaryKey::ALL.to_string(),
+        format!("{}/0", Pubkey::default())
+    );
+}
+
+#[test]
+fn test_top_owners() {
+    let owners =
+        [1u8, 2, 3, 4].map(|i| PartialPubkeyByBits::new(Pubkey::new_from_array([i; 32]), 8));
+    let owner_totals = HashMap::from([
+        (owners[0], (10, 100)),
+        (owners[1], (5, 1000)),
+        (owners[2], (10, 50)),
+        (owners[3], (1, 1)),
+    ]);
+    let top_owners = |ranking: OwnerRanking, top_k| {
+        let mut top_owners = Vec::from_iter(ranking.top_owners(&owner_totals, top_k));
+        top_owners.sort_unstable();
+        top_owners
+    };
+    assert_eq!(
+        top_owners(OwnerRanking::Accounts, 2),
+        vec![owners[0], owners[2]]
+    );
+    assert_eq!(
+        top_owners(OwnerRanking::Bytes, 2),
+        vec![owners[0], owners[1]]
+    );
+    // ties go to the smallest owner
+    assert_eq!(top_owners(OwnerRanking::Accounts, 1), vec![owners[0]]);
+    assert_eq!(top_owners(OwnerRanking::Bytes, 0), vec![]);
+    assert_eq!(top_owners(OwnerRanking::Bytes, 10).len(), 4);
+}
@@ -0,0 +1,356 @@
+use {
+    crate::{
+        append_vec_iter,
+        archived::{ArchiveFileSnapshotExtractor, ArchiveSnapshotExtractor},
+        manifest::SnapshotManifest,
+        AccountsDbFields, AppendVec, AppendVecIterator, ReadProgressTracking,
+        SerializableAccountStorageEntry, SnapshotError, SnapshotExtractor, SnapshotResult,
+    },
+    log::info,
+    solana_accounts_db::account_storage::meta::StoredMetaWriteVersion,
+    solana_runtime::snapshot_archive_info::{
+        IncrementalSnapshotArchiveInfo, SnapshotArchiveInfoGetter,
+    },
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    std::{collections::HashMap, path::Path, time::Instant},
+};
+
+/// Newest (slot, write version, AppendVec id, offset) of every account stored in an incremental
+/// snapshot.
+///
+/// Held in memory, about 100 bytes per account of the incremental snapshot. `LatestAccountsIndex`
+/// built on the combined snapshots also drops the overwritten accounts and spills to disk.
+pub struct LatestIncrementalAccounts(HashMap<Pubkey, (Slot, StoredMetaWriteVersion, u64, usize)>);
+
+impl LatestIncrementalAccounts {
+    /// Reads all the AppendVecs of an incremental snapshot, the extractor cannot be reused.
+    pub fn from_extractor<E: SnapshotExtractor>(incremental: &mut E) -> SnapshotResult<Self> {
+        let start = Instant::now();
+        let mut latest: HashMap<Pubkey, (Slot, StoredMetaWriteVersion, u64, usize)> =
+            HashMap::new();
+        for append_vec in incremental.iter() {
+            let append_vec = append_vec?;
+            for handle in append_vec_iter(&append_vec) {
+                let Some(stored) = handle.access() else {
+                    continue;
+                };
+                let version = (
+                    append_vec.slot(),
+                    stored.meta.write_version_obsolete,
+                    append_vec.id(),
+                    stored.offset,
+                );
+                latest
+                    .entry(stored.meta.pubkey)
+                    .and_modify(|current| *current = version.max(*current))
+                    .or_insert(version);
+            }
+        }
+        info!(
+            "Indexed {} incremental snapshot accounts in {:?}",
+            latest.len(),
+            start.elapsed()
+        );
+        Ok(Self(latest))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns false if a newer version of the account is stored in the incremental snapshot.
+    pub fn is_latest(&self, pubkey: &Pubkey, slot: Slot, id: u64, offset: usize) -> bool {
+        match self.0.get(pubkey) {
+            Some((latest_slot, _, latest_id, latest_offset)) => {
+                (*latest_slot, *latest_id, *latest_offset) == (slot, id, offset)
+            }
+            None => true,
+        }
+    }
+
+    fn filter(&self, append_vec: AppendVec) -> SnapshotResult<Option<AppendVec>> {
+        let (slot, id) = (append_vec.slot(), append_vec.id());
+        Ok(append_vec
+            .filter(|account| self.is_latest(&account.meta.pubkey, slot, id, account.offset))?)
+    }
+}
+
+/// Extracts account data from a full snapshot combined with an incremental snapshot based on it.
+///
+/// AppendVecs of the incremental snapshot are yielded before the ones of the full snapshot.
+pub struct IncrementalSnapshotExtractor<Full, Incremental>
+where
+    Full: SnapshotExtractor,
+    Incremental: SnapshotExtractor,
+{
+    full: Full,
+    incremental: Incremental,
+    latest_accounts: Option<LatestIncrementalAccounts>,
+}
+
+impl<Full, Incremental> SnapshotExtractor for IncrementalSnapshotExtractor<Full, Incremental>
+where
+    Full: SnapshotExtractor,
+    Incremental: SnapshotExtractor,
+{
+    fn iter(&mut self) -> AppendVecIterator<'_> {
+        let Self {
+            full,
+            incremental,
+            latest_accounts,
+        } = self;
+        let latest_accounts = latest_accounts.as_ref();
+        Box::new(
+            incremental.iter().chain(full.iter()).filter_map(
+                move |append_vec| match latest_accounts {
+                    Some(latest_accounts) => append_vec
+                        .and_then(|append_vec| latest_accounts.filter(append_vec))
+                        .transpose(),
+                    None => Some(append_vec),
+                },
+            ),
+        )
+    }
+
+    fn accounts_db_fields(&self) -> &AccountsDbFields<SerializableAccountStorageEntry> {
+        self.incremental.accounts_db_fields()
+    }
+
+    fn manifest(&self) -> &SnapshotManifest {
+        self.incremental.manifest()
+    }
+}
+
+impl<Full, Incremental> IncrementalSnapshotExtractor<Full, Incremental>
+where
+    Full: SnapshotExtractor,
+    Incremental: SnapshotExtractor,
+{
+    /// Combines both snapshots, `base_slot` is the slot of the full snapshot the incremental
+    /// snapshot was built on. When `latest_accounts` is given only the newest version of the
+    /// accounts stored in the incremental snapshot is yielded.
+    pub fn new(
+        full: Full,
+        incremental: Incremental,
+        base_slot: Slot,
+        latest_accounts: Option<LatestIncrementalAccounts>,
+    ) -> SnapshotResult<Self> {
+        if base_slot != full.slot() || incremental.slot() <= base_slot {
+            return Err(SnapshotError::MismatchedIncrementalSnapshot {
+                full_slot: full.slot(),
+                incremental_slot: incremental.slot(),
+                base_slot,
+            });
+        }
+        Ok(Self {
+            full,
+            incremental,
+            latest_accounts,
+        })
+    }
+
+    pub fn full_slot(&self) -> Slot {
+        self.full.slot()
+    }
+
+    pub fn full_manifest(&self) -> &SnapshotManifest {
+        self.full.manifest()
+    }
+}
+
+impl IncrementalSnapshotExtractor<ArchiveFileSnapshotExtractor, ArchiveFileSnapshotExtractor> {
+    /// Opens a full and an incremental snapshot archive, the base slot of the incremental
+    /// snapshot is read from its file name. With `latest_accounts_only` the incremental archive
+    /// is read twice and its accounts are indexed in memory, see [`LatestIncrementalAccounts`].
+    pub fn open(
+        full_path: &Path,
+        incremental_path: &Path,
+        latest_accounts_only: bool,
+        progress_tracking: &dyn ReadProgressTracking,
+    ) -> SnapshotResult<Self> {
+        let info = IncrementalSnapshotArchiveInfo::new_from_path(incremental_path.to_path_buf())
+            .map_err(|e| {
+                SnapshotError::UnknownIncrementalBaseSlot(
+                    incremental_path.display().to_string(),
+                    e.to_string(),
+                )
+            })?;
+        let full = ArchiveSnapshotExtractor::open(full_path, progress_tracking)?;
+        // checked before reading the incremental archive, and again once it is opened
+        if info.base_slot() != full.slot() {
+            return Err(SnapshotError::MismatchedIncrementalSnapshot {
+                full_slot: full.slot(),
+                incremental_slot: info.slot(),
+                base_slot: info.base_slot(),
+            });
+        }
+
+        let latest_accounts = if latest_accounts_only {
+            let mut incremental =
+                ArchiveSnapshotExtractor::open(incremental_path, progress_tracking)?;
+            Some(LatestIncrementalAccounts::from_extractor(&mut incremental)?)
+        } else {
+            None
+        };
+        let incremental = ArchiveSnapshotExtractor::open(incremental_path, progress_tracking)?;
+        Self::new(full, incremental, info.base_slot(), latest_accounts)
+    }
+}
+
+/// A full snapshot archive, optionally combined with an incremental snapshot archive.
+pub enum SnapshotArchivesExtractor {
+    Full(Box<ArchiveFileSnapshotExtractor>),
+    Incremental(
+        Box<
+            IncrementalSnapshotExtractor<
+                ArchiveFileSnapshotExtractor,
+                ArchiveFileSnapshotExtractor,
+            >,
+        >,
+    ),
+}
+
+impl SnapshotArchivesExtractor {
+    /// Opens a full snapshot archive and the incremental snapshot archive based on it if given,
+    /// see [`IncrementalSnapshotExtractor::open`] for `latest_accounts_only`.
+    pub fn open(
+        full_path: &Path,
+        incremental_path: Option<&Path>,
+        latest_accounts_only: bool,
+        progress_tracking: &dyn ReadProgressTracking,
+    ) -> SnapshotResult<Self> {
+        Ok(match incremental_path {
+            Some(incremental_path) => {
+                Self::Incremental(Box::new(IncrementalSnapshotExtractor::open(
+                    full_path,
+                    incremental_path,
+                    latest_accounts_only,
+                    progress_tracking,
+                )?))
+            }
+            None => Self::Full(Box::new(ArchiveSnapshotExtractor::open(
+                full_path,
+                progress_tracking,
+            )?)),
+        })
+    }
+}
+
+impl SnapshotExtractor for SnapshotArchivesExtractor {
+    fn iter(&mut self) -> AppendVecIterator<'_> {
+        match self {
+            Self::Full(extractor) => extractor.iter(),
+            Self::Incremental(extractor) => extractor.iter(),
+        }
+    }
+
+    fn accounts_db_fields(&self) -> &AccountsDbFields<SerializableAccountStorageEntry> {
+        match self {
+            Self::Full(extractor) => extractor.accounts_db_fields(),
+            Self::Incremental(extractor) => extractor.accounts_db_fields(),
+        }
+    }
+
+    fn manifest(&self) -> &SnapshotManifest {
+        match self {
+            Self::Full(extractor) => extractor.manifest(),
+            Self::Incremental(extractor) => extractor.manifest(),
+        }
+    }
+}
+
+#[test]
+fn test_incremental_snapshot_extractor() {
+    use {
+        crate::{append_vec::test_append_vec, TestExtractor},
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    let owner = Pubkey::new_unique();
+    let [a, b, c, d] = [(); 4].map(|_| Pubkey::new_unique());
+    let full = || {
+        TestExtractor::new(
+            10,
+            vec![
+                test_append_vec(5, 1, &[(a, 1, 1, owner, b"a5"), (b, 2, 1, owner, b"b5")]),
+                test_append_vec(5, 2, &[(c, 3, 1, owner, b"c5")]),
+            ],
+        )
+    };
+    // both storages of slot 15 hold a version of `a` at offset 0
+    let incremental = || {
+        TestExtractor::new(
+            20,
+            vec![
+                test_append_vec(15, 3, &[(a, 10, 1, owner, b"a15")]),
+                test_append_vec(
+                    15,
+                    4,
+                    &[(a, 11, 1, owner, b"a15'"), (d, 12, 1, owner, b"d15")],
+                ),
+            ],
+        )
+    };
+    let accounts = |extractor: &mut IncrementalSnapshotExtractor<TestExtractor, TestExtractor>| {
+        let mut accounts = Vec::new();
+        for append_vec in extractor.iter() {
+            let append_vec = append_vec.unwrap();
+            for handle in append_vec_iter(&append_vec) {
+                let stored = handle.access().unwrap();
+                accounts.push((stored.meta.pubkey, stored.data.to_vec()));
+            }
+        }
+        accounts
+    };
+
+    let mut extractor = IncrementalSnapshotExtractor::new(full(), incremental(), 10, None).unwrap();
+    assert_eq!((extractor.slot(), extractor.full_slot()), (20, 10));
+    assert_eq!(
+        accounts(&mut extractor),
+        vec![
+            (a, b"a15".to_vec()),
+            (a, b"a15'".to_vec()),
+            (d, b"d15".to_vec()),
+            (a, b"a5".to_vec()),
+            (b, b"b5".to_vec()),
+            (c, b"c5".to_vec()),
+        ]
+    );
+
+    let latest_accounts = LatestIncrementalAccounts::from_extractor(&mut incremental()).unwrap();
+    assert_eq!(latest_accounts.len(), 2);
+    let mut extractor =
+        IncrementalSnapshotExtractor::new(full(), incremental(), 10, Some(latest_accounts))
+            .unwrap();
+    assert_eq!(
+        accounts(&mut extractor),
+        vec![
+            (a, b"a15'".to_vec()),
+            (d, b"d15".to_vec()),
+            (b, b"b5".to_vec()),
+            (c, b"c5".to_vec()),
+        ]
+    );
+
+    assert!(matches!(
+        IncrementalSnapshotExtractor::new(incremental(), full(), 20, None),
+        Err(SnapshotError::MismatchedIncrementalSnapshot {
+            full_slot: 20,
+            incremental_slot: 10,
+            base_slot: 20,
+        })
+    ));
+    // built on another full snapshot
+    assert!(matches!(
+        IncrementalSnapshotExtractor::new(full(), incremental(), 8, None),
+        Err(SnapshotError::MismatchedIncrementalSnapshot {
+            full_slot: 10,
+            incremental_slot: 20,
+            base_slot: 8,
+        })
+    ));
+}
@@ -1,21 +1,39 @@
 use {
     crate::{
         append_vec::{AppendVec, StoredAccountMeta},
+        manifest::SnapshotManifest,
         solana::{
             deserialize_from, AccountsDbFields, DeserializableVersionedBank,
             SerializableAccountStorageEntry,
         },
     },
+    solana_sdk::clock::Slot,
     std::{ffi::OsStr, io::Read, path::Path, str::FromStr},
     thiserror::Error,
 };
 
 pub mod append_vec;
 pub mod archived;
+pub mod breakdown;
+pub mod codec;
+pub mod compression_stats;
+pub mod dedup;
+pub mod dictionary;
+pub mod dictionary_builder;
+pub mod dictionary_key;
+pub mod incremental;
+pub mod manifest;
 pub mod parallel;
 pub mod partial_pubkey;
 pub mod partial_pubkey_by_bits;
+pub mod progress;
+pub mod report;
+pub mod sampling;
+pub mod size_selection;
 pub mod solana;
+pub mod split;
+pub mod sweep;
+pub mod tester;
 pub mod unpacked;
 
 const SNAPSHOTS_DIR: &str = "snapshots";
@@ -34,6 +52,14 @@ pub enum SnapshotError {
     UnexpectedAppendVec,
     #[error("Failed to create read progress tracking: {0}")]
     ReadProgressTracking(String),
+    #[error("Incremental snapshot at slot {incremental_slot} (base slot {base_slot}) does not extend full snapshot at slot {full_slot}")]
+    MismatchedIncrementalSnapshot {
+        full_slot: Slot,
+        incremental_slot: Slot,
+        base_slot: Slot,
+    },
+    #[error("Failed to read the base slot of incremental snapshot archive {0}: {1}")]
+    UnknownIncrementalBaseSlot(String, String),
 }
 
 pub type SnapshotResult<T> = Result<T, SnapshotError>;
@@ -42,6 +68,56 @@ pub type AppendVecIterator<'a> = Box<dyn Iterator<Item = SnapshotResult<AppendVe
 
 pub trait SnapshotExtractor: Sized {
     fn iter(&mut self) -> AppendVecIterator<'_>;
+
+    fn accounts_db_fields(&self) -> &AccountsDbFields<SerializableAccountStorageEntry>;
+
+    /// Metadata of the snapshot the AppendVecs come from.
+    fn manifest(&self) -> &SnapshotManifest;
+
+    /// Slot of the snapshot.
+    fn slot(&self) -> Slot {
+        self.accounts_db_fields().2
+    }
+}
+
+/// In-memory snapshot at `slot` yielding its AppendVecs once.
+#[cfg(test)]
+pub(crate) struct TestExtractor {
+    append_vecs: Vec<AppendVec>,
+    accounts_db_fields: AccountsDbFields<SerializableAccountStorageEntry>,
+    manifest: SnapshotManifest,
+}
+
+#[cfg(test)]
+impl TestExtractor {
+    pub(crate) fn new(slot: Slot, append_vecs: Vec<AppendVec>) -> Self {
+        Self {
+            append_vecs,
+            accounts_db_fields: AccountsDbFields {
+                2: slot,
+                ..AccountsDbFields::default()
+            },
+            manifest: SnapshotManifest {
+                slot,
+                ..SnapshotManifest::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+impl SnapshotExtractor for TestExtractor {
+    fn iter(&mut self) -> AppendVecIterator<'_> {
+        Box::new(self.append_vecs.drain(..).map(Ok))
+    }
+
+    fn accounts_db_fields(&self) -> &AccountsDbFields<SerializableAccountStorageEntry> {
+        &self.accounts_db_fields
+    }
+
+    fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
 }
 
 fn parse_append_vec_name(name: &OsStr) -> Option<(u64, u64)> {
@@ -65,7 +141,7 @@ pub fn append_vec_iter(append_vec: &AppendVec) -> impl Iterator<Item = StoredAcc
         })
     })
     .take_while(|account| account.is_some())
-    .filter_map(|account| account)
+    .flatten()
 }
 
 pub struct StoredAccountMetaHandle<'a> {
@@ -83,6 +159,7 @@ impl<'a> StoredAccountMetaHandle<'a> {
     }
 }
 
+/// Wraps the raw readers of snapshot files to report how much of them has been read.
 pub trait ReadProgressTracking {
     fn new_read_progress_tracker(
         &self,
@@ -92,7 +169,8 @@ pub trait ReadProgressTracking {
     ) -> SnapshotResult<Box<dyn Read>>;
 }
 
-struct NoopReadProgressTracking {}
+/// Does not track anything.
+pub struct NoopReadProgressTracking {}
 
 impl ReadProgressTracking for NoopReadProgressTracking {
     fn new_read_progress_tracker(
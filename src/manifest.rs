@@ -0,0 +1,76 @@
+use {
+    crate::solana::{
+        AccountsDbFields, BankHashInfo, DeserializableVersionedBank,
+        SerializableAccountStorageEntry,
+    },
+    serde::{Deserialize, Serialize},
+    solana_sdk::{
+        clock::{Epoch, Slot},
+        hash::Hash,
+    },
+};
+
+/// Bank and accounts DB metadata read from the snapshot manifest, identifies the snapshot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub slot: Slot,
+    pub parent_slot: Slot,
+    pub epoch: Epoch,
+    pub block_height: u64,
+    pub bank_hash: Hash,
+    pub parent_hash: Hash,
+    pub capitalization: u64,
+    pub accounts_data_len: u64,
+    pub accounts_db: AccountsDbSummary,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountsDbSummary {
+    pub slot: Slot,
+    pub write_version: u64,
+    /// Number of slots with at least one storage.
+    pub storage_slots: usize,
+    pub storages: usize,
+    pub max_storages_per_slot: usize,
+    /// Sum of the used length of all storages.
+    pub storages_len: u64,
+    pub bank_hash_info: BankHashInfo,
+}
+
+impl SnapshotManifest {
+    pub fn new(
+        bank: &DeserializableVersionedBank,
+        accounts_db_fields: &AccountsDbFields<SerializableAccountStorageEntry>,
+    ) -> Self {
+        Self {
+            slot: bank.slot,
+            parent_slot: bank.parent_slot,
+            epoch: bank.epoch,
+            block_height: bank.block_height,
+            bank_hash: bank.hash,
+            parent_hash: bank.parent_hash,
+            capitalization: bank.capitalization,
+            accounts_data_len: bank.accounts_data_len,
+            accounts_db: AccountsDbSummary::new(accounts_db_fields),
+        }
+    }
+}
+
+impl AccountsDbSummary {
+    pub fn new(accounts_db_fields: &AccountsDbFields<SerializableAccountStorageEntry>) -> Self {
+        let storages_per_slot = &accounts_db_fields.0;
+        Self {
+            slot: accounts_db_fields.2,
+            write_version: accounts_db_fields.1,
+            storage_slots: storages_per_slot.len(),
+            storages: storages_per_slot.values().map(Vec::len).sum(),
+            max_storages_per_slot: storages_per_slot.values().map(Vec::len).max().unwrap_or(0),
+            storages_len: storages_per_slot
+                .values()
+                .flatten()
+                .map(|storage| storage.accounts_current_len as u64)
+                .sum(),
+            bank_hash_info: accounts_db_fields.3.clone(),
+        }
+    }
+}
@@ -1,5 +1,13 @@
 use {
     crate::{AppendVec, AppendVecIterator},
+    std::{
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            mpsc, Condvar, Mutex, PoisonError,
+        },
+        thread,
+    },
     tokio::task::JoinSet,
 };
 
@@ -8,21 +16,23 @@ pub trait AppendVecConsumer {
     async fn on_append_vec(&mut self, append_vec: AppendVec) -> anyhow::Result<()>;
 }
 
+/// Feeds the append vecs to at most `num_threads` consumers running concurrently and returns
+/// the consumers once every append vec has been consumed, so that their results can be merged.
 pub async fn par_iter_append_vecs<F, A>(
     iterator: AppendVecIterator<'_>,
     create_consumer: F,
     num_threads: usize,
-) -> anyhow::Result<()>
+) -> anyhow::Result<Vec<A>>
 where
-    F: Fn() -> A,
+    F: Fn() -> anyhow::Result<A>,
     A: AppendVecConsumer + Send + 'static,
 {
     let mut tasks = JoinSet::new();
     for append_vec in iterator {
-        let mut consumer = if tasks.len() >= num_threads {
+        let mut consumer = if tasks.len() >= num_threads.max(1) {
             tasks.join_next().await.expect("checked")??
         } else {
-            create_consumer()
+            create_consumer()?
         };
 
         tasks.spawn(async move {
@@ -30,9 +40,376 @@ where
             Ok::<_, anyhow::Error>(consumer)
         });
     }
+    let mut consumers = Vec::with_capacity(tasks.len());
     while let Some(result) = tasks.join_next().await {
-        result??;
+        consumers.push(result??);
     }
 
-    Ok(())
+    Ok(consumers)
+}
+
+/// Synchronous consumer of append vecs, one per thread, merged once all are consumed.
+pub trait AppendVecFolder: Send + Sized {
+    fn fold(&mut self, append_vec: AppendVec) -> anyhow::Result<()>;
+
+    /// Adds the results of a folder of another thread.
+    fn merge(&mut self, other: Self);
+}
+
+/// Folds the append vecs on a pool of `num_threads` threads and merges the folders in the
+/// order they were created.
+///
+/// Append vecs are read on the calling thread. The first error, of the iterator or of a folder,
+/// stops the iteration and is returned once the append vecs being folded are done, a panic of a
+/// folder is resumed the same way.
+pub fn par_fold_append_vecs<F, A>(
+    iterator: AppendVecIterator<'_>,
+    create_folder: F,
+    num_threads: usize,
+) -> anyhow::Result<A>
+where
+    F: Fn() -> anyhow::Result<A> + Sync,
+    A: AppendVecFolder,
+{
+    let num_threads = num_threads.max(1);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()?;
+    let (sender, receiver) = mpsc::sync_channel::<AppendVec>(num_threads);
+    let receiver = Mutex::new(receiver);
+    let cancelled = AtomicBool::new(false);
+    let first_error = Mutex::new(None);
+    let fail = |e: anyhow::Error| {
+        first_error.lock().unwrap().get_or_insert(e);
+        cancelled.store(true, Ordering::Relaxed);
+    };
+    // resumed once the other threads are done
+    let first_panic = Mutex::new(None);
+    let folders = Mutex::new(Vec::with_capacity(num_threads));
+
+    pool.in_place_scope(|scope| {
+        for index in 0..num_threads {
+            let (create_folder, receiver, cancelled, fail, first_panic, folders) = (
+                &create_folder,
+                &receiver,
+                &cancelled,
+                &fail,
+                &first_panic,
+                &folders,
+            );
+            scope.spawn(move |_| {
+                let mut folder = create_folder().map_err(fail).ok();
+                // keeps receiving once cancelled so that the sender is never blocked
+                loop {
+                    let received = receiver.lock().unwrap().recv();
+                    let Ok(append_vec) = received else {
+                        break;
+                    };
+                    let Some(current) = folder.as_mut() else {
+                        continue;
+                    };
+                    if cancelled.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    match panic::catch_unwind(AssertUnwindSafe(|| current.fold(append_vec))) {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => {
+                            fail(e);
+                            folder = None;
+                        }
+                        Err(payload) => {
+                            cancelled.store(true, Ordering::Relaxed);
+                            first_panic.lock().unwrap().get_or_insert(payload);
+                            folder = None;
+                        }
+                    }
+                }
+                if let Some(folder) = folder {
+                    folders.lock().unwrap().push((index, folder));
+                }
+            });
+        }
+
+        for append_vec in iterator {
+            if cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+            match append_vec {
+                Ok(append_vec) => sender
+                    .send(append_vec)
+                    .expect("receiver outlives the sender"),
+                Err(e) => {
+                    fail(e.into());
+                    break;
+                }
+            }
+        }
+        drop(sender);
+    });
+
+    if let Some(payload) = first_panic.into_inner().unwrap() {
+        panic::resume_unwind(payload);
+    }
+    if let Some(e) = first_error.into_inner().unwrap() {
+        return Err(e);
+    }
+    let mut folders = folders.into_inner().unwrap();
+    folders.sort_unstable_by_key(|(index, _)| *index);
+    let mut folders = folders.into_iter().map(|(_, folder)| folder);
+    let mut result = folders.next().expect("one folder per thread");
+    for folder in folders {
+        result.merge(folder);
+    }
+    Ok(result)
+}
+
+/// Maps the items on `num_threads` threads, results are in the order of the items.
+///
+/// The summed `cost` of the items mapped at the same time stays within `budget`, an item costing
+/// more than the budget is mapped alone. A panic of `f` stops the mapping and is resumed.
+pub fn par_map_within_budget<T, R, C, F>(
+    items: &[T],
+    num_threads: usize,
+    budget: usize,
+    cost: C,
+    f: F,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    C: Fn(&T) -> usize + Sync,
+    F: Fn(&T) -> R + Sync,
+{
+    /// Releases the cost of an item even when mapping it panics.
+    struct Reservation<'a> {
+        cost: usize,
+        in_flight: &'a Mutex<usize>,
+        released: &'a Condvar,
+        cancelled: &'a AtomicBool,
+    }
+
+    impl Drop for Reservation<'_> {
+        fn drop(&mut self) {
+            if thread::panicking() {
+                self.cancelled.store(true, Ordering::Relaxed);
+            }
+            *self
+                .in_flight
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) -= self.cost;
+            self.released.notify_all();
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let in_flight = Mutex::new(0usize);
+    let released = Condvar::new();
+    let cancelled = AtomicBool::new(false);
+    let worker = || {
+        let mut results = Vec::new();
+        while !cancelled.load(Ordering::Relaxed) {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let Some(item) = items.get(index) else {
+                break;
+            };
+            let cost = cost(item);
+            {
+                let mut in_flight = released
+                    .wait_while(in_flight.lock().unwrap(), |in_flight| {
+                        !cancelled.load(Ordering::Relaxed)
+                            && *in_flight > 0
+                            && in_flight.saturating_add(cost) > budget
+                    })
+                    .unwrap();
+                if cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+                *in_flight += cost;
+            }
+            let _reservation = Reservation {
+                cost,
+                in_flight: &in_flight,
+                released: &released,
+                cancelled: &cancelled,
+            };
+            results.push((index, f(item)));
+        }
+        results
+    };
+
+    let mut results = thread::scope(|scope| {
+        let workers = (0..num_threads.clamp(1, items.len().max(1)))
+            .map(|_| scope.spawn(worker))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|payload| panic::resume_unwind(payload))
+            })
+            .collect::<Vec<_>>()
+    });
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_par_iter_append_vecs() {
+    use {crate::append_vec::test_owner_append_vec, solana_sdk::pubkey::Pubkey};
+
+    #[derive(Default)]
+    struct CountingConsumer {
+        append_vecs: usize,
+        accounts: usize,
+    }
+
+    #[async_trait::async_trait]
+    impl AppendVecConsumer for CountingConsumer {
+        async fn on_append_vec(&mut self, append_vec: AppendVec) -> anyhow::Result<()> {
+            self.append_vecs += 1;
+            self.accounts += crate::append_vec_iter(&append_vec).count();
+            Ok(())
+        }
+    }
+
+    let owner = Pubkey::new_unique();
+    let append_vecs = (0..10u64)
+        .map(|slot| Ok(test_owner_append_vec(slot, slot, owner, &[1, 2, 3])))
+        .collect::<Vec<_>>();
+    let consumers = par_iter_append_vecs(
+        Box::new(append_vecs.into_iter()),
+        || Ok(CountingConsumer::default()),
+        3,
+    )
+    .await
+    .unwrap();
+    assert!(consumers.len() <= 3);
+    assert_eq!(
+        consumers
+            .iter()
+            .map(|consumer| consumer.append_vecs)
+            .sum::<usize>(),
+        10
+    );
+    assert_eq!(
+        consumers
+            .iter()
+            .map(|consumer| consumer.accounts)
+            .sum::<usize>(),
+        45
+    );
+}
+
+#[test]
+fn test_par_map_within_budget() {
+    let items = (0..100usize).collect::<Vec<_>>();
+    let running = Mutex::new(0usize);
+    let results = par_map_within_budget(
+        &items,
+        4,
+        100,
+        |item| item * 3,
+        |item| {
+            {
+                let mut running = running.lock().unwrap();
+                *running += item * 3;
+                // only the items over the budget run alone
+                assert!(*running <= 100 || *running == item * 3);
+            }
+            thread::yield_now();
+            *running.lock().unwrap() -= item * 3;
+            item * 2
+        },
+    );
+    assert_eq!(
+        results,
+        items.iter().map(|item| item * 2).collect::<Vec<_>>()
+    );
+    assert!(par_map_within_budget(&[] as &[usize], 4, 0, |_| 0, |item| *item).is_empty());
+
+    // the other items wait for the cost of the panicking one to be released
+    let mapped = AtomicUsize::new(0);
+    let panicked = panic::catch_unwind(|| {
+        par_map_within_budget(
+            &items,
+            4,
+            10,
+            |_| 10,
+            |item| {
+                assert_ne!(*item, 3, "cannot map 3");
+                mapped.fetch_add(1, Ordering::Relaxed);
+            },
+        )
+    });
+    let payload = panicked.err().unwrap();
+    assert!(payload
+        .downcast_ref::<String>()
+        .unwrap()
+        .contains("cannot map 3"));
+    assert!(mapped.load(Ordering::Relaxed) < items.len());
+}
+
+#[test]
+fn test_par_fold_append_vecs() {
+    use {
+        crate::{append_vec::test_owner_append_vec, SnapshotError},
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    #[derive(Default)]
+    struct CountingFolder {
+        slots: Vec<u64>,
+        accounts: usize,
+    }
+
+    impl AppendVecFolder for CountingFolder {
+        fn fold(&mut self, append_vec: AppendVec) -> anyhow::Result<()> {
+            anyhow::ensure!(append_vec.slot() != 13, "unlucky slot");
+            assert_ne!(append_vec.slot(), 42, "cursed slot");
+            self.slots.push(append_vec.slot());
+            self.accounts += crate::append_vec_iter(&append_vec).count();
+            Ok(())
+        }
+
+        fn merge(&mut self, other: Self) {
+            self.slots.extend(other.slots);
+            self.accounts += other.accounts;
+        }
+    }
+
+    let owner = Pubkey::new_unique();
+    let append_vecs = |slots: std::ops::Range<u64>| {
+        Box::new(slots.map(move |slot| Ok(test_owner_append_vec(slot, slot, owner, &[1, 2, 3]))))
+    };
+    let mut folder =
+        par_fold_append_vecs(append_vecs(0..10), || Ok(CountingFolder::default()), 3).unwrap();
+    folder.slots.sort_unstable();
+    assert_eq!(folder.slots, (0..10).collect::<Vec<_>>());
+    assert_eq!(folder.accounts, 45);
+
+    let error = par_fold_append_vecs(append_vecs(0..1000), || Ok(CountingFolder::default()), 3)
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "unlucky slot");
+    let error = par_fold_append_vecs(
+        Box::new(append_vecs(0..5).chain([Err(SnapshotError::UnexpectedAppendVec)])),
+        || Ok(CountingFolder::default()),
+        2,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(error.to_string(), "Unexpected AppendVec");
+    let error = par_fold_append_vecs(
+        append_vecs(0..5),
+        || Err::<CountingFolder, _>(anyhow::anyhow!("no folder")),
+        2,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(error.to_string(), "no folder");
+    let panicked = std::panic::catch_unwind(|| {
+        par_fold_append_vecs(append_vecs(14..1000), || Ok(CountingFolder::default()), 3)
+    });
+    assert!(panicked.is_err());
 }
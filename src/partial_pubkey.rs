@@ -52,7 +52,7 @@ impl<const SIZE: usize, const OFFSET: usize> From<&Pubkey> for PartialPubkey<SIZ
 }
 
 impl<const SIZE: usize, const OFFSET: usize> PartialPubkey<SIZE, OFFSET> {
-    pub fn to_bytes(&self) -> &[u8; SIZE] {
+    pub const fn to_bytes(&self) -> &[u8; SIZE] {
         &self.0
     }
 }
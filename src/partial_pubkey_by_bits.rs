@@ -1,10 +1,18 @@
 use serde::{de::Visitor, Deserialize, Serialize};
-use solana_sdk::pubkey::Pubkey;
+use solana_sdk::pubkey::{Pubkey, PUBKEY_BYTES};
+use std::fmt::Display;
 
+/// Number of bits of a pubkey.
+pub const MAX_NB_BITS: u16 = (PUBKEY_BYTES * 8) as u16;
+
+/// Bucket of the pubkeys sharing their first `nb_bits` bits.
+///
+/// `bits` holds these bits, every following bit is zero. Buckets with the same `nb_bits`
+/// partition the pubkey space into `2^nb_bits` buckets, `nb_bits == 0` is a single bucket.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct PartialPubkeyByBits {
-    pub nb_bits: u8,
-    pub bits: u8,
+    pub nb_bits: u16,
+    pub bits: [u8; PUBKEY_BYTES],
 }
 
 impl<'de> Deserialize<'de> for PartialPubkeyByBits {
@@ -18,20 +26,44 @@ impl<'de> Deserialize<'de> for PartialPubkeyByBits {
             type Value = PartialPubkeyByBits;
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a bytes of size {SIZE:?}")
+                formatter.write_str("a little endian u16 followed by the prefix bytes")
             }
 
             fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                Ok(PartialPubkeyByBits {
-                    nb_bits: v[0],
-                    bits: v[1],
-                })
+                if let [legacy_nb_bits, legacy_bits] = *v {
+                    return PartialPubkeyByBits::from_legacy(legacy_nb_bits, legacy_bits)
+                        .ok_or_else(|| E::invalid_value(serde::de::Unexpected::Bytes(v), &self));
+                }
+                let (nb_bits, prefix) = match v {
+                    [lo, hi, prefix @ ..] => (u16::from_le_bytes([*lo, *hi]), prefix),
+                    _ => return Err(E::invalid_length(v.len(), &self)),
+                };
+                if nb_bits > MAX_NB_BITS || prefix.len() != encoded_prefix_len(nb_bits) {
+                    return Err(E::invalid_value(serde::de::Unexpected::Bytes(v), &self));
+                }
+                let mut bits = [0; PUBKEY_BYTES];
+                bits[..prefix.len()].copy_from_slice(prefix);
+                Ok(PartialPubkeyByBits::new(
+                    Pubkey::new_from_array(bits),
+                    nb_bits,
+                ))
             }
         }
-        Ok(deserializer.deserialize_bytes(BytesVisitor {})?)
+        deserializer.deserialize_bytes(BytesVisitor {})
+    }
+}
+
+/// At least one prefix byte is written so that encoded keys are never 2 bytes long like the
+/// legacy encoding.
+const fn encoded_prefix_len(nb_bits: u16) -> usize {
+    let len = (nb_bits as usize + 7) / 8;
+    if len == 0 {
+        1
+    } else {
+        len
     }
 }
 
@@ -40,24 +72,67 @@ impl Serialize for PartialPubkeyByBits {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_bytes(&[self.nb_bits, self.bits])
+        let prefix_len = encoded_prefix_len(self.nb_bits);
+        let mut bytes = [0; 2 + PUBKEY_BYTES];
+        bytes[..2].copy_from_slice(&self.nb_bits.to_le_bytes());
+        bytes[2..2 + prefix_len].copy_from_slice(&self.bits[..prefix_len]);
+        serializer.serialize_bytes(&bytes[..2 + prefix_len])
     }
 }
 
 impl PartialPubkeyByBits {
-    pub fn new(value: Pubkey, nb_bits: u8) -> Self {
+    /// The bucket of every pubkey.
+    pub const ALL: Self = Self {
+        nb_bits: 0,
+        bits: [0; PUBKEY_BYTES],
+    };
+
+    /// Keeps the first `nb_bits` bits of `value`, `nb_bits` is capped at [`MAX_NB_BITS`].
+    pub fn new(value: Pubkey, nb_bits: u16) -> Self {
+        let nb_bits = nb_bits.min(MAX_NB_BITS);
+        let full_bytes = nb_bits as usize / 8;
+        let remaining_bits = (nb_bits % 8) as u8;
+        let value = value.to_bytes();
+        let mut bits = [0; PUBKEY_BYTES];
+        bits[..full_bytes].copy_from_slice(&value[..full_bytes]);
+        if remaining_bits != 0 {
+            bits[full_bytes] = value[full_bytes] & !create_bit_mask(8 - remaining_bits);
+        }
+        Self { nb_bits, bits }
+    }
+
+    /// Converts a key of the original encoding, `first_byte | create_bit_mask(nb_bits)`, which
+    /// kept the first `8 - nb_bits` bits of the pubkey.
+    pub fn from_legacy(nb_bits: u8, bits: u8) -> Option<Self> {
         if nb_bits > 8 {
-            panic!("nb_bits should be less than 8")
+            return None;
         }
-        let mask = create_bit_mask(nb_bits);
-        Self {
-            nb_bits,
-            bits: value.to_bytes()[0] | mask,
+        let mut first_bytes = [0; PUBKEY_BYTES];
+        first_bytes[0] = bits;
+        Some(Self::new(
+            Pubkey::new_from_array(first_bytes),
+            8 - nb_bits as u16,
+        ))
+    }
+
+    pub fn contains(&self, pubkey: &Pubkey) -> bool {
+        Self::new(*pubkey, self.nb_bits) == *self
+    }
+}
+
+/// The owner for a full pubkey, `<bits>/<nb_bits>` otherwise.
+impl Display for PartialPubkeyByBits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pubkey = Pubkey::new_from_array(self.bits);
+        if self.nb_bits == MAX_NB_BITS {
+            write!(f, "{pubkey}")
+        } else {
+            write!(f, "{pubkey}/{}", self.nb_bits)
         }
     }
 }
 
-pub fn create_bit_mask(nb_bits: u8) -> u8 {
+pub const fn create_bit_mask(nb_bits: u8) -> u8 {
     if nb_bits == 0 {
         0
     } else if nb_bits == 8 {
@@ -79,3 +154,70 @@ fn test_mask() {
     assert_eq!(create_bit_mask(7), 127);
     assert_eq!(create_bit_mask(8), 255);
 }
+
+#[test]
+fn test_prefix_buckets() {
+    use {
+        rand::{rngs::StdRng, Rng, SeedableRng},
+        std::collections::HashSet,
+    };
+
+    fn bit(pubkey: &Pubkey, index: usize) -> bool {
+        pubkey.as_ref()[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let pubkeys = (0..512)
+        .map(|_| Pubkey::new_from_array(rng.gen()))
+        .collect::<Vec<_>>();
+    for nb_bits in 0..=MAX_NB_BITS {
+        let mut buckets = HashSet::new();
+        for (i, pubkey) in pubkeys.iter().enumerate() {
+            let key = PartialPubkeyByBits::new(*pubkey, nb_bits);
+            buckets.insert(key);
+            assert_eq!(key.nb_bits, nb_bits);
+            assert!(key.contains(pubkey));
+            for index in 0..MAX_NB_BITS as usize {
+                let expected = index < nb_bits as usize && bit(pubkey, index);
+                assert_eq!(bit(&Pubkey::new_from_array(key.bits), index), expected);
+            }
+            if nb_bits <= 8 {
+                let mask = !create_bit_mask(8 - nb_bits as u8);
+                assert_eq!(key.bits[0], pubkey.as_ref()[0] & mask);
+            }
+
+            // same bucket exactly when the first nb_bits bits are equal
+            let other = &pubkeys[(i + 1) % pubkeys.len()];
+            let same_prefix =
+                (0..nb_bits as usize).all(|index| bit(pubkey, index) == bit(other, index));
+            assert_eq!(key.contains(other), same_prefix);
+
+            let encoded = bincode::serialize(&key).unwrap();
+            assert_eq!(
+                bincode::deserialize::<PartialPubkeyByBits>(&encoded).unwrap(),
+                key
+            );
+        }
+        if nb_bits <= 6 {
+            assert_eq!(buckets.len(), 1 << nb_bits);
+        }
+    }
+    assert_eq!(
+        PartialPubkeyByBits::new(pubkeys[0], MAX_NB_BITS + 1),
+        PartialPubkeyByBits::new(pubkeys[0], MAX_NB_BITS)
+    );
+
+    for legacy_nb_bits in 0..=8u8 {
+        for pubkey in &pubkeys {
+            let legacy_bits = pubkey.as_ref()[0] | create_bit_mask(legacy_nb_bits);
+            // bincode encoding of a byte slice: u64 length then the bytes
+            let mut legacy = 2u64.to_le_bytes().to_vec();
+            legacy.extend([legacy_nb_bits, legacy_bits]);
+            let key = bincode::deserialize::<PartialPubkeyByBits>(&legacy).unwrap();
+            assert_eq!(
+                key,
+                PartialPubkeyByBits::new(*pubkey, 8 - legacy_nb_bits as u16)
+            );
+        }
+    }
+}
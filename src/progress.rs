@@ -0,0 +1,137 @@
+use {
+    crate::{ReadProgressTracking, SnapshotResult},
+    log::info,
+    std::{
+        io::Read,
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+};
+
+const ONE_MB: f64 = (1024 * 1024) as f64;
+
+/// Periodically logs read throughput and estimated time to completion.
+pub struct LoggingReadProgressTracking {
+    pub interval: Duration,
+}
+
+impl Default for LoggingReadProgressTracking {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ReadProgressTracking for LoggingReadProgressTracking {
+    fn new_read_progress_tracker(
+        &self,
+        path: &Path,
+        rd: Box<dyn Read>,
+        file_len: u64,
+    ) -> SnapshotResult<Box<dyn Read>> {
+        let now = Instant::now();
+        Ok(Box::new(LoggingReader {
+            inner: rd,
+            path: path.to_path_buf(),
+            file_len,
+            bytes_read: 0,
+            interval: self.interval,
+            start: now,
+            last_log: now,
+            finished: false,
+        }))
+    }
+}
+
+struct LoggingReader {
+    inner: Box<dyn Read>,
+    path: PathBuf,
+    file_len: u64,
+    bytes_read: u64,
+    interval: Duration,
+    start: Instant,
+    last_log: Instant,
+    /// The end of the file was reached and logged.
+    finished: bool,
+}
+
+/// Time left to read `remaining` bytes at `throughput` bytes per second, `None` when it cannot be
+/// estimated.
+fn eta(remaining: u64, throughput: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(remaining as f64 / throughput).ok()
+}
+
+impl LoggingReader {
+    fn log_progress(&self) {
+        let elapsed = self.start.elapsed();
+        let throughput = self.bytes_read as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let eta = match eta(self.file_len.saturating_sub(self.bytes_read), throughput) {
+            Some(eta) => format!("{:?}", Duration::from_secs(eta.as_secs())),
+            None => "unknown".to_string(),
+        };
+        info!(
+            "{:?}: read {:.0}/{:.0} MB ({:.1}%) at {:.1} MB/s, elapsed {:?}, ETA {eta}",
+            self.path,
+            self.bytes_read as f64 / ONE_MB,
+            self.file_len as f64 / ONE_MB,
+            100.0 * self.bytes_read as f64 / (self.file_len as f64).max(1.0),
+            throughput / ONE_MB,
+            Duration::from_secs(elapsed.as_secs()),
+        );
+    }
+
+    /// Logs at most once per interval, and once more when the end of the file is reached.
+    fn should_log(&mut self, end_of_file: bool) -> bool {
+        if end_of_file {
+            return !std::mem::replace(&mut self.finished, true);
+        }
+        if self.last_log.elapsed() < self.interval {
+            return false;
+        }
+        self.last_log = Instant::now();
+        true
+    }
+}
+
+impl Read for LoggingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.bytes_read += len as u64;
+        if self.should_log(len == 0) {
+            self.log_progress();
+        }
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_logging_reader() {
+    assert_eq!(eta(100, 10.0), Some(Duration::from_secs(10)));
+    assert_eq!(eta(0, 0.0), None);
+    assert_eq!(eta(100, 0.0), None);
+    assert_eq!(eta(u64::MAX, f64::MIN_POSITIVE), None);
+
+    let now = Instant::now();
+    let mut reader = LoggingReader {
+        inner: Box::new(std::io::Cursor::new(vec![1u8; 10])),
+        path: PathBuf::from("test"),
+        file_len: 10,
+        bytes_read: 0,
+        interval: Duration::from_secs(3600),
+        start: now,
+        last_log: now,
+        finished: false,
+    };
+    assert!(!reader.should_log(false));
+    let mut data = Vec::new();
+    assert_eq!(reader.read_to_end(&mut data).unwrap(), 10);
+    assert_eq!(reader.bytes_read, 10);
+    // the end of the file was logged by read_to_end
+    assert!(reader.finished);
+    assert!(!reader.should_log(true));
+    assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+
+    reader.interval = Duration::ZERO;
+    assert!(reader.should_log(false));
+}
@@ -0,0 +1,85 @@
+use {
+    serde::Serialize,
+    std::{fmt::Display, io::Write, str::FromStr},
+};
+
+/// Row of a report written as csv.
+pub trait CsvRecord {
+    const HEADER: &'static [&'static str];
+
+    fn record(&self) -> Vec<String>;
+}
+
+/// File format of the reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+impl ReportFormat {
+    pub const ALL: [ReportFormat; 2] = [ReportFormat::Csv, ReportFormat::Json];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+        }
+    }
+
+    /// Format matching the extension of `path`, csv when unknown.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".json") {
+            ReportFormat::Json
+        } else {
+            ReportFormat::Csv
+        }
+    }
+
+    pub fn write<W: Write, T: Serialize + CsvRecord>(
+        self,
+        mut writer: W,
+        rows: &[T],
+    ) -> std::io::Result<()> {
+        match self {
+            ReportFormat::Csv => {
+                writeln!(writer, "{}", T::HEADER.join(","))?;
+                for row in rows {
+                    let record = row.record();
+                    let fields = record.iter().map(|field| csv_field(field));
+                    writeln!(writer, "{}", fields.collect::<Vec<_>>().join(","))?;
+                }
+            }
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, rows)?;
+                writeln!(writer)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ReportFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == s)
+            .ok_or_else(|| format!("Unknown report format {s}"))
+    }
+}
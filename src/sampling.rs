@@ -0,0 +1,540 @@
+use {
+    crate::dictionary::TrainingStats,
+    sha2::{Digest, Sha256},
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    std::{
+        cmp::{Ordering, Reverse},
+        collections::{BinaryHeap, HashMap},
+        fmt::Display,
+        hash::Hash,
+        str::FromStr,
+    },
+};
+
+/// Probability of an account to be sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleWeighting {
+    Uniform,
+    /// Proportional to the data length, large accounts dominate the compressed size.
+    DataLen,
+}
+
+impl SampleWeighting {
+    pub const ALL: [SampleWeighting; 2] = [SampleWeighting::Uniform, SampleWeighting::DataLen];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            SampleWeighting::Uniform => "uniform",
+            SampleWeighting::DataLen => "data-len",
+        }
+    }
+}
+
+impl Display for SampleWeighting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SampleWeighting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SampleWeighting::ALL
+            .into_iter()
+            .find(|weighting| weighting.name() == s)
+            .ok_or_else(|| format!("Unknown sample weighting {s}"))
+    }
+}
+
+/// Id of the version of an account stored at `slot`, see [`ReservoirSampler::draw`].
+pub fn account_id(pubkey: &Pubkey, slot: Slot) -> [u8; 40] {
+    let mut id = [0; 40];
+    id[..32].copy_from_slice(pubkey.as_ref());
+    id[32..].copy_from_slice(&slot.to_le_bytes());
+    id
+}
+
+/// Samples of one key, concatenated as expected by the zstd trainer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Samples {
+    pub samples: Vec<u8>,
+    pub sizes: Vec<usize>,
+    /// Accounts seen for the key.
+    pub accounts: u64,
+    /// Data bytes seen for the key.
+    pub bytes: u64,
+}
+
+impl Samples {
+    pub fn add(&mut self, data: &[u8]) {
+        self.sizes.push(data.len());
+        self.samples.extend_from_slice(data);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.sizes.iter().scan(0, |offset, size| {
+            let sample = &self.samples[*offset..*offset + size];
+            *offset += size;
+            Some(sample)
+        })
+    }
+
+    /// Adds the samples of `other` until one of the limits is reached.
+    pub fn merge(&mut self, other: Samples, max_samples: usize, max_bytes: usize) {
+        self.accounts += other.accounts;
+        self.bytes += other.bytes;
+        for sample in other.iter() {
+            if self.sizes.len() >= max_samples || self.samples.len() + sample.len() >= max_bytes {
+                break;
+            }
+            self.add(sample);
+        }
+    }
+
+    pub fn stats(&self) -> TrainingStats {
+        TrainingStats {
+            accounts: self.accounts,
+            samples: self.sizes.len() as u64,
+            sample_bytes: self.samples.len() as u64,
+            ..TrainingStats::default()
+        }
+    }
+}
+
+/// Length below which the priorities of the evicted samples are not removed.
+const MIN_COMPACTION_LEN: usize = 1024;
+
+/// Sample kept while the one with the lowest priority is evicted first.
+struct Sample {
+    priority: f64,
+    data: Vec<u8>,
+}
+
+impl PartialEq for Sample {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Sample {}
+
+impl PartialOrd for Sample {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sample {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.total_cmp(&other.priority)
+    }
+}
+
+/// Priority of a sample of the reservoir `key`, the lowest is evicted first to stay within the
+/// memory budget.
+struct KeyPriority<K> {
+    priority: f64,
+    key: K,
+}
+
+impl<K> PartialEq for KeyPriority<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K> Eq for KeyPriority<K> {}
+
+impl<K> PartialOrd for KeyPriority<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K> Ord for KeyPriority<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.total_cmp(&other.priority)
+    }
+}
+
+struct Reservoir {
+    /// Lowest priority on top.
+    samples: BinaryHeap<Reverse<Sample>>,
+    /// Highest priority evicted, samples at or below it are not kept.
+    threshold: f64,
+    sample_bytes: usize,
+    accounts: u64,
+    bytes: u64,
+}
+
+impl Reservoir {
+    /// Removes the lowest priority sample, returns its length.
+    fn evict(&mut self) -> usize {
+        let Reverse(sample) = self.samples.pop().unwrap();
+        self.threshold = self.threshold.max(sample.priority);
+        self.sample_bytes -= sample.data.len();
+        sample.data.len()
+    }
+}
+
+/// Per key weighted reservoir sampling (Efraimidis-Spirakis A-Res).
+///
+/// Every account gets the priority `ln(u) / weight` with `u` uniform in (0, 1], each key keeps
+/// its accounts of highest priority, as many as fit in `max_samples` and `max_bytes_per_key`
+/// bytes. While the samples of all keys exceed `memory_budget`, the sample of lowest priority
+/// of any key is evicted and no account at or below its priority is kept anymore.
+///
+/// `u` is a hash of the seed and the account and evictions only depend on priorities, so the
+/// samples do not depend on the order of the accounts. Samplers with the same budget fed with
+/// disjoint accounts merge into the samples of a single sampler fed with all the accounts, the
+/// memory used while sampling on several threads is bounded by the sum of their budgets.
+pub struct ReservoirSampler<K> {
+    seed: u64,
+    weighting: SampleWeighting,
+    max_samples: usize,
+    max_bytes_per_key: usize,
+    memory_budget: usize,
+    sample_bytes: usize,
+    reservoirs: HashMap<K, Reservoir>,
+    /// Priorities of the samples of all keys, lowest on top, including evicted samples until
+    /// they are popped or compacted.
+    lowest: BinaryHeap<Reverse<KeyPriority<K>>>,
+    /// Length of `lowest` above which its evicted samples are removed.
+    compaction_len: usize,
+    /// Highest priority evicted to stay within the memory budget, samples of every key at or
+    /// below it are not kept.
+    threshold: f64,
+}
+
+impl<K: Copy + Eq + Hash + Ord> ReservoirSampler<K> {
+    pub fn new(
+        seed: u64,
+        weighting: SampleWeighting,
+        max_samples: usize,
+        max_bytes_per_key: usize,
+        memory_budget: usize,
+    ) -> Self {
+        Self {
+            seed,
+            weighting,
+            max_samples,
+            max_bytes_per_key,
+            memory_budget,
+            sample_bytes: 0,
+            reservoirs: HashMap::new(),
+            lowest: BinaryHeap::new(),
+            compaction_len: MIN_COMPACTION_LEN,
+            threshold: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Uniform draw in (0, 1] of the account identified by `id`, the same for every key the
+    /// account is added to.
+    pub fn draw(&self, id: &[u8]) -> f64 {
+        let hash = Sha256::new()
+            .chain_update(self.seed.to_le_bytes())
+            .chain_update(id)
+            .finalize();
+        let hash = u64::from_le_bytes(hash[..8].try_into().unwrap());
+        // 53 random bits, the precision of an f64
+        1.0 - (hash >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Adds an account with its [`ReservoirSampler::draw`].
+    pub fn add(&mut self, key: K, draw: f64, data: &[u8]) {
+        let weight = match self.weighting {
+            SampleWeighting::Uniform => 1.0,
+            SampleWeighting::DataLen => data.len().max(1) as f64,
+        };
+        let reservoir = self.reservoir(key);
+        reservoir.accounts += 1;
+        reservoir.bytes += data.len() as u64;
+        if data.len() > self.max_bytes_per_key {
+            return;
+        }
+        self.insert(key, draw.ln() / weight, data.len(), || data.to_vec());
+    }
+
+    /// Adds the accounts of `other`, sampled with the same parameters.
+    pub fn merge(&mut self, other: Self) {
+        // what other evicted is not part of the samples of both either
+        self.threshold = self.threshold.max(other.threshold);
+        self.evict_below_threshold();
+        for (key, other) in other.reservoirs {
+            let reservoir = self.reservoir(key);
+            reservoir.accounts += other.accounts;
+            reservoir.bytes += other.bytes;
+            reservoir.threshold = reservoir.threshold.max(other.threshold);
+            let mut evicted = 0;
+            while matches!(
+                reservoir.samples.peek(),
+                Some(Reverse(lowest)) if lowest.priority <= reservoir.threshold
+            ) {
+                evicted += reservoir.evict();
+            }
+            self.sample_bytes -= evicted;
+            for Reverse(sample) in other.samples {
+                self.insert(key, sample.priority, sample.data.len(), || sample.data);
+            }
+        }
+    }
+
+    fn reservoir(&mut self, key: K) -> &mut Reservoir {
+        self.reservoirs.entry(key).or_insert_with(|| Reservoir {
+            samples: BinaryHeap::new(),
+            threshold: f64::NEG_INFINITY,
+            sample_bytes: 0,
+            accounts: 0,
+            bytes: 0,
+        })
+    }
+
+    /// Keeps the sample if its priority is among the highest of its reservoir and of all the
+    /// samples within the memory budget.
+    fn insert(&mut self, key: K, priority: f64, len: usize, data: impl FnOnce() -> Vec<u8>) {
+        let reservoir = self.reservoirs.get_mut(&key).unwrap();
+        if priority <= reservoir.threshold.max(self.threshold) {
+            return;
+        }
+        reservoir.samples.push(Reverse(Sample {
+            priority,
+            data: data(),
+        }));
+        reservoir.sample_bytes += len;
+        self.sample_bytes += len;
+        while reservoir.samples.len() > self.max_samples
+            || reservoir.sample_bytes > self.max_bytes_per_key
+        {
+            self.sample_bytes -= reservoir.evict();
+        }
+        if priority > reservoir.threshold {
+            self.lowest.push(Reverse(KeyPriority { priority, key }));
+        }
+        while self.sample_bytes > self.memory_budget {
+            let Some(Reverse(lowest)) = self.lowest.peek() else {
+                break;
+            };
+            if lowest.priority <= self.reservoirs[&lowest.key].threshold {
+                // already evicted to stay within the limits of the reservoir
+                self.lowest.pop();
+                continue;
+            }
+            self.threshold = self.threshold.max(lowest.priority);
+            self.evict_below_threshold();
+        }
+        if self.lowest.len() > self.compaction_len {
+            self.compact();
+        }
+    }
+
+    /// Evicts the samples of every key at or below the threshold.
+    fn evict_below_threshold(&mut self) {
+        while let Some(Reverse(lowest)) = self.lowest.peek() {
+            if lowest.priority > self.threshold {
+                break;
+            }
+            let Reverse(KeyPriority { priority, key }) = self.lowest.pop().unwrap();
+            let reservoir = self.reservoirs.get_mut(&key).unwrap();
+            // otherwise already evicted to stay within the limits of the reservoir
+            if priority > reservoir.threshold {
+                self.sample_bytes -= reservoir.evict();
+            }
+        }
+    }
+
+    /// Removes the priorities of the samples evicted from their reservoir.
+    fn compact(&mut self) {
+        self.lowest = self
+            .reservoirs
+            .iter()
+            .flat_map(|(key, reservoir)| {
+                reservoir.samples.iter().map(|Reverse(sample)| {
+                    Reverse(KeyPriority {
+                        priority: sample.priority,
+                        key: *key,
+                    })
+                })
+            })
+            .collect();
+        self.compaction_len = (self.lowest.len() * 2).max(MIN_COMPACTION_LEN);
+    }
+
+    /// Bytes of all the samples kept.
+    pub const fn sample_bytes(&self) -> usize {
+        self.sample_bytes
+    }
+
+    /// Samples of every key, highest priority first.
+    pub fn into_samples(self) -> HashMap<K, Samples> {
+        self.reservoirs
+            .into_iter()
+            .map(|(key, reservoir)| {
+                let mut samples = Samples {
+                    accounts: reservoir.accounts,
+                    bytes: reservoir.bytes,
+                    ..Samples::default()
+                };
+                for Reverse(sample) in reservoir.samples.into_sorted_vec() {
+                    samples.add(&sample.data);
+                }
+                (key, samples)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_reservoir_sampler() {
+    let new_sampler = |seed, weighting, memory_budget| {
+        ReservoirSampler::new(seed, weighting, 100, 10_000, memory_budget)
+    };
+    let add = |sampler: &mut ReservoirSampler<u32>, i: u32| {
+        let len = if i % 2 == 0 { 4 } else { 16 };
+        let draw = sampler.draw(&i.to_le_bytes());
+        sampler.add(i % 3, draw, &vec![(i % 251) as u8; len]);
+    };
+    let sample = |seed, weighting, memory_budget| {
+        let mut sampler = new_sampler(seed, weighting, memory_budget);
+        for i in 0..10_000u32 {
+            add(&mut sampler, i);
+            assert!(sampler.sample_bytes() <= memory_budget);
+        }
+        sampler.into_samples()
+    };
+
+    let samples = sample(0, SampleWeighting::Uniform, usize::MAX);
+    assert_eq!(samples, sample(0, SampleWeighting::Uniform, usize::MAX));
+    assert_ne!(samples, sample(1, SampleWeighting::Uniform, usize::MAX));
+    // the same accounts split between two samplers
+    let mut even = new_sampler(0, SampleWeighting::Uniform, usize::MAX);
+    let mut odd = new_sampler(0, SampleWeighting::Uniform, usize::MAX);
+    for i in 0..10_000u32 {
+        add(if i % 2 == 0 { &mut even } else { &mut odd }, i);
+    }
+    even.merge(odd);
+    assert_eq!(even.into_samples(), samples);
+    for key in 0..3 {
+        let samples = &samples[&key];
+        assert_eq!(samples.sizes.len(), 100);
+        assert_eq!(samples.accounts, 3334 - (key != 0) as u64);
+        // not the first accounts of the key
+        assert!(samples.iter().any(|sample| sample[0] as u32 >= 100));
+        let large = samples.sizes.iter().filter(|size| **size == 16).count();
+        assert!((25..75).contains(&large), "{large}");
+    }
+
+    let weighted = sample(0, SampleWeighting::DataLen, usize::MAX);
+    for samples in weighted.values() {
+        let large = samples.sizes.iter().filter(|size| **size == 16).count();
+        assert!(large > 65, "{large}");
+    }
+
+    let bounded = sample(0, SampleWeighting::Uniform, 1000);
+    let total = bounded
+        .values()
+        .map(|samples| samples.samples.len())
+        .sum::<usize>();
+    assert!(total <= 1000);
+    assert!(bounded.values().all(|samples| !samples.sizes.is_empty()));
+    let mut even = new_sampler(0, SampleWeighting::Uniform, 1000);
+    let mut odd = new_sampler(0, SampleWeighting::Uniform, 1000);
+    for i in (0..10_000u32).rev() {
+        add(if i % 2 == 0 { &mut even } else { &mut odd }, i);
+    }
+    even.merge(odd);
+    assert!(even.sample_bytes() <= 1000);
+    assert_eq!(even.into_samples(), bounded);
+}
+
+#[test]
+fn test_reservoir_sampler_threads() {
+    use {
+        crate::{
+            append_vec::{test_append_vec, AppendVec},
+            append_vec_iter,
+            parallel::{par_fold_append_vecs, AppendVecFolder},
+        },
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    struct SamplingFolder(ReservoirSampler<Pubkey>);
+
+    impl AppendVecFolder for SamplingFolder {
+        fn fold(&mut self, append_vec: AppendVec) -> anyhow::Result<()> {
+            for handle in append_vec_iter(&append_vec) {
+                let stored = handle.access().unwrap();
+                let draw = self
+                    .0
+                    .draw(&account_id(&stored.meta.pubkey, append_vec.slot()));
+                self.0.add(stored.account_meta.owner, draw, stored.data);
+                assert!(self.0.sample_bytes() <= 5000);
+            }
+            Ok(())
+        }
+
+        fn merge(&mut self, other: Self) {
+            self.0.merge(other.0);
+            assert!(self.0.sample_bytes() <= 5000);
+        }
+    }
+
+    let owners = [
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    ];
+    let data = (0..100u8).map(|i| vec![i; i as usize]).collect::<Vec<_>>();
+    let accounts = (0..20usize)
+        .map(|slot| {
+            (0..50)
+                .map(|i| {
+                    let owner = owners[(slot + i) % owners.len()];
+                    let data = &data[(slot * 7 + i * 13) % data.len()];
+                    (Pubkey::new_unique(), i as u64, 1, owner, &data[..])
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    // both the per key and the global limits evict samples
+    let sample = |threads| {
+        let append_vecs = accounts
+            .iter()
+            .enumerate()
+            .map(|(slot, accounts)| Ok(test_append_vec(slot as u64, 0, accounts)));
+        par_fold_append_vecs(
+            Box::new(append_vecs),
+            || {
+                Ok(SamplingFolder(ReservoirSampler::new(
+                    0,
+                    SampleWeighting::DataLen,
+                    200,
+                    3000,
+                    5000,
+                )))
+            },
+            threads,
+        )
+        .unwrap()
+        .0
+        .into_samples()
+    };
+
+    let samples = sample(1);
+    let sample_bytes = samples
+        .values()
+        .map(|samples| samples.samples.len())
+        .sum::<usize>();
+    assert!(sample_bytes <= 5000);
+    assert!(samples.values().all(|samples| samples.sizes.len() < 200));
+    assert_eq!(
+        samples
+            .values()
+            .map(|samples| samples.accounts)
+            .sum::<u64>(),
+        1000
+    );
+    for threads in [2, 4, 7] {
+        assert_eq!(sample(threads), samples, "{threads} threads");
+    }
+}
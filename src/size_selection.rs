@@ -0,0 +1,318 @@
+use {
+    crate::{
+        codec::{AccountCodec, CodecKind, CodecResult},
+        dictionary::TrainingStats,
+        dictionary_builder::{DictionaryBuilder, ZstdTrainingParams},
+        sampling::Samples,
+    },
+    std::{
+        cmp::Ordering,
+        collections::{BTreeMap, BinaryHeap},
+    },
+};
+
+/// Minimum number of training samples to train a dictionary.
+pub const MIN_TRAINING_SAMPLES: usize = 32;
+
+/// Dictionary trained with one of the candidate sizes.
+pub struct Candidate {
+    /// Requested size, the trained dictionary may be smaller.
+    pub dictionary_size: usize,
+    pub dictionary: Vec<u8>,
+    /// Compressed size of the validation samples with the dictionary.
+    pub compressed_bytes: u64,
+}
+
+/// Candidate dictionaries of one key, evaluated on samples not used for training.
+pub struct KeyCandidates {
+    pub stats: TrainingStats,
+    pub validation_bytes: u64,
+    /// Compressed size of the validation samples without dictionary.
+    pub baseline_compressed_bytes: u64,
+    /// Sorted by requested size.
+    pub candidates: Vec<Candidate>,
+}
+
+impl KeyCandidates {
+    /// Trains a dictionary of every size, every `validation_every`-th sample is held out to
+    /// measure the compressed size with `codec`. Sizes that fail to train are skipped. Returns
+    /// `None` when there are too few samples to train, no size could be trained or
+    /// `validation_every` is below 2.
+    pub fn train(
+        samples: &Samples,
+        dictionary_sizes: &[usize],
+        builder: DictionaryBuilder,
+        params: &ZstdTrainingParams,
+        codec: CodecKind,
+        level: Option<i32>,
+        validation_every: usize,
+    ) -> CodecResult<Option<Self>> {
+        if validation_every < 2 {
+            return Ok(None);
+        }
+        let mut training = Samples::default();
+        let mut validation = Samples::default();
+        for (i, sample) in samples.iter().enumerate() {
+            if i % validation_every == validation_every - 1 {
+                validation.add(sample);
+            } else {
+                training.add(sample);
+            }
+        }
+        if training.sizes.len() < MIN_TRAINING_SAMPLES || validation.sizes.is_empty() {
+            return Ok(None);
+        }
+
+        let validation_bytes = validation.samples.len() as u64;
+        let baseline_compressed_bytes =
+            compressed_len(&mut *codec.create(level, None)?, &validation)?;
+        let mut candidates = Vec::with_capacity(dictionary_sizes.len());
+        for &dictionary_size in dictionary_sizes {
+            // a size zstd cannot train, e.g. below its minimum dictionary size, does not drop
+            // the other sizes
+            let dictionary = match builder.build(&training, dictionary_size, params) {
+                Ok(dictionary) => dictionary,
+                Err(e) => {
+                    log::warn!("skipping {builder} dictionary of {dictionary_size} bytes: {e}");
+                    continue;
+                }
+            };
+            let mut dictionary_codec = codec.create(level, Some(&dictionary))?;
+            candidates.push(Candidate {
+                dictionary_size,
+                compressed_bytes: compressed_len(&mut *dictionary_codec, &validation)?,
+                dictionary,
+            });
+        }
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+        candidates.sort_by_key(|candidate| candidate.dictionary_size);
+
+        Ok(Some(Self {
+            stats: TrainingStats {
+                accounts: samples.accounts,
+                samples: training.sizes.len() as u64,
+                sample_bytes: training.samples.len() as u64,
+                ..TrainingStats::default()
+            },
+            validation_bytes,
+            baseline_compressed_bytes,
+            candidates,
+        }))
+    }
+
+    /// Training stats of the candidate at `index`.
+    pub fn stats(&self, index: usize) -> TrainingStats {
+        let candidate = &self.candidates[index];
+        TrainingStats {
+            dictionary_size: candidate.dictionary_size as u64,
+            validation_bytes: self.validation_bytes,
+            validation_compressed_bytes: candidate.compressed_bytes,
+            ..self.stats
+        }
+    }
+
+    /// Compressed bytes of the candidate at `index`, or without dictionary.
+    fn compressed_bytes(&self, index: Option<usize>) -> u64 {
+        index.map_or(self.baseline_compressed_bytes, |index| {
+            self.candidates[index].compressed_bytes
+        })
+    }
+
+    fn dictionary_len(&self, index: Option<usize>) -> usize {
+        index.map_or(0, |index| self.candidates[index].dictionary.len())
+    }
+
+    /// Candidate saving the most validation bytes per added dictionary byte over `current`,
+    /// among those whose added bytes fit in `remaining`.
+    fn best_upgrade(&self, current: Option<usize>, remaining: usize) -> Option<Upgrade> {
+        let compressed_bytes = self.compressed_bytes(current);
+        let dictionary_len = self.dictionary_len(current);
+        (0..self.candidates.len())
+            .filter_map(|index| {
+                let candidate = &self.candidates[index];
+                let cost = candidate.dictionary.len().checked_sub(dictionary_len)?;
+                let saved = compressed_bytes.checked_sub(candidate.compressed_bytes)?;
+                (saved > 0 && cost <= remaining).then_some(Upgrade { saved, cost, index })
+            })
+            .max()
+    }
+}
+
+fn compressed_len(codec: &mut dyn AccountCodec, samples: &Samples) -> CodecResult<u64> {
+    let mut output = Vec::new();
+    let mut compressed_len = 0;
+    for sample in samples.iter() {
+        output.resize(codec.max_compressed_len(sample.len()), 0);
+        compressed_len += codec.compress_into(sample, &mut output)? as u64;
+    }
+    Ok(compressed_len)
+}
+
+/// Replacing the dictionary of a key by the candidate at `index`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Upgrade {
+    saved: u64,
+    cost: usize,
+    index: usize,
+}
+
+impl Upgrade {
+    /// Orders by saved bytes per dictionary byte, free upgrades first.
+    fn ratio_cmp(&self, other: &Self) -> Ordering {
+        (self.saved as u128 * other.cost as u128).cmp(&(other.saved as u128 * self.cost as u128))
+    }
+}
+
+impl PartialOrd for Upgrade {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Upgrade {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.ratio_cmp(other)
+            .then(self.saved.cmp(&other.saved))
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// Picks at most one candidate per key so that the dictionaries fit in `budget` bytes.
+///
+/// Greedy multiple choice knapsack: the upgrade saving the most validation bytes per dictionary
+/// byte is applied until none fits. Keys without a selected candidate are missing.
+pub fn select_sizes<K: Copy + Ord>(
+    candidates: &BTreeMap<K, KeyCandidates>,
+    budget: usize,
+) -> BTreeMap<K, usize> {
+    let mut remaining = budget;
+    let mut selected = BTreeMap::new();
+    let mut upgrades = candidates
+        .iter()
+        .filter_map(|(key, candidates)| Some((candidates.best_upgrade(None, remaining)?, *key)))
+        .collect::<BinaryHeap<_>>();
+    while let Some((upgrade, key)) = upgrades.pop() {
+        let candidates = &candidates[&key];
+        let current = selected.get(&key).copied();
+        if upgrade.cost > remaining {
+            // the budget shrunk since the upgrade was computed, cheaper ones have lower ratios
+            if let Some(upgrade) = candidates.best_upgrade(current, remaining) {
+                upgrades.push((upgrade, key));
+            }
+            continue;
+        }
+        remaining -= upgrade.cost;
+        selected.insert(key, upgrade.index);
+        if let Some(upgrade) = candidates.best_upgrade(Some(upgrade.index), remaining) {
+            upgrades.push((upgrade, key));
+        }
+    }
+    selected
+}
+
+#[test]
+fn test_select_sizes() {
+    let key_candidates = |baseline, candidates: &[(usize, u64)]| KeyCandidates {
+        stats: TrainingStats::default(),
+        validation_bytes: baseline,
+        baseline_compressed_bytes: baseline,
+        candidates: candidates
+            .iter()
+            .map(|&(dictionary_size, compressed_bytes)| Candidate {
+                dictionary_size,
+                dictionary: vec![0; dictionary_size],
+                compressed_bytes,
+            })
+            .collect(),
+    };
+    let candidates = BTreeMap::from([
+        // saves 10 then 1 byte per dictionary byte
+        (0, key_candidates(10_000, &[(100, 9_000), (200, 8_900)])),
+        // saves 2 bytes per dictionary byte
+        (1, key_candidates(10_000, &[(100, 9_800), (200, 9_600)])),
+        // dictionaries do not help
+        (2, key_candidates(10_000, &[(100, 10_000), (200, 10_100)])),
+    ]);
+
+    assert_eq!(select_sizes(&candidates, 0), BTreeMap::new());
+    assert_eq!(select_sizes(&candidates, 150), BTreeMap::from([(0, 0)]));
+    assert_eq!(
+        select_sizes(&candidates, 300),
+        BTreeMap::from([(0, 0), (1, 1)])
+    );
+    assert_eq!(
+        select_sizes(&candidates, 10_000),
+        BTreeMap::from([(0, 1), (1, 1)])
+    );
+
+    let samples = {
+        let mut samples = Samples::default();
+        for i in 0..400u32 {
+            let mut data = b"common account layout ".repeat(8);
+            data.extend_from_slice(&i.to_le_bytes());
+            samples.add(&data);
+        }
+        samples
+    };
+    let trained = KeyCandidates::train(
+        &samples,
+        &[2048, 1024],
+        DictionaryBuilder::Zstd,
+        &ZstdTrainingParams::default(),
+        CodecKind::Zstd,
+        Some(3),
+        10,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(trained.stats.samples, 360);
+    assert_eq!(
+        trained
+            .candidates
+            .iter()
+            .map(|candidate| candidate.dictionary_size)
+            .collect::<Vec<_>>(),
+        vec![1024, 2048]
+    );
+    assert!(trained.candidates[0].compressed_bytes < trained.baseline_compressed_bytes);
+    assert_eq!(trained.stats(1).dictionary_size, 2048);
+    // below the minimum size of a zstd dictionary
+    let untrainable = 128;
+    let train = |dictionary_sizes: &[usize]| {
+        KeyCandidates::train(
+            &samples,
+            dictionary_sizes,
+            DictionaryBuilder::Zstd,
+            &ZstdTrainingParams::default(),
+            CodecKind::Zstd,
+            Some(3),
+            10,
+        )
+        .unwrap()
+    };
+    let trained = train(&[untrainable, 1024]).unwrap();
+    assert_eq!(
+        trained
+            .candidates
+            .iter()
+            .map(|candidate| candidate.dictionary_size)
+            .collect::<Vec<_>>(),
+        vec![1024]
+    );
+    assert!(train(&[untrainable]).is_none());
+    for validation_every in [0, 1] {
+        assert!(KeyCandidates::train(
+            &samples,
+            &[1024],
+            DictionaryBuilder::Zstd,
+            &ZstdTrainingParams::default(),
+            CodecKind::Zstd,
+            Some(3),
+            validation_every,
+        )
+        .unwrap()
+        .is_none());
+    }
+}
@@ -120,6 +120,73 @@ pub struct AccountsDbFields<T>(
 
 pub type SerializedAppendVecId = usize;
 
+/// Serializes the snapshot manifest of a default bank at `slot` whose storages are given as
+/// `(slot, id, accounts_current_len)`.
+#[cfg(test)]
+pub(crate) fn test_snapshot_manifest(slot: Slot, storages: &[(Slot, usize, usize)]) -> Vec<u8> {
+    // bincode writes a struct as the tuple of its fields, see DeserializableVersionedBank
+    let bank = (
+        (
+            BlockhashQueue::default(),
+            AncestorsForSerialization::default(),
+            Hash::default(),
+            Hash::default(),
+            0 as Slot,
+            HardForks::default(),
+            0u64,
+            0u64,
+            0u64,
+            0u64,
+            0u64,
+            None::<u64>,
+            0u64,
+            0u128,
+            0 as UnixTimestamp,
+            0f64,
+        ),
+        (
+            0u64,
+            slot,
+            0 as Epoch,
+            0u64,
+            Pubkey::default(),
+            0u64,
+            FeeCalculator::default(),
+            FeeRateGovernor::default(),
+            0u64,
+            RentCollector::default(),
+            EpochSchedule::default(),
+            Inflation::default(),
+            Stakes::<Delegation>::default(),
+            (
+                HashSet::<Pubkey>::new(),
+                HashSet::<Pubkey>::new(),
+                HashMap::<Pubkey, u64>::new(),
+            ),
+            HashMap::<Epoch, EpochStakes>::new(),
+            false,
+        ),
+    );
+    let mut storages_per_slot = HashMap::<Slot, Vec<(usize, usize)>>::new();
+    for &(storage_slot, id, accounts_current_len) in storages {
+        storages_per_slot
+            .entry(storage_slot)
+            .or_default()
+            .push((id, accounts_current_len));
+    }
+    let accounts_db_fields = (
+        storages_per_slot,
+        0 as StoredMetaWriteVersion,
+        slot,
+        BankHashInfo::default(),
+        Vec::<Slot>::new(),
+        Vec::<(Slot, Hash)>::new(),
+    );
+    let mut manifest = bincode::serialize(&bank).unwrap();
+    manifest.extend(bincode::serialize(&accounts_db_fields).unwrap());
+    manifest
+}
+
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
 pub struct SerializableAccountStorageEntry {
     pub id: SerializedAppendVecId,
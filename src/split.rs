@@ -0,0 +1,117 @@
+use {
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    solana_sdk::pubkey::Pubkey,
+    std::{fmt::Display, str::FromStr},
+};
+
+/// Deterministic train/test split of the accounts by pubkey.
+///
+/// An account is held out for testing when the hash of its pubkey modulo `modulus` is zero, the
+/// split only depends on the pubkey so it is the same for every snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldoutSplit {
+    pub modulus: u32,
+}
+
+impl HoldoutSplit {
+    /// Every account would be held out with a modulus of 1.
+    pub fn new(modulus: u32) -> Self {
+        assert!(modulus >= 2, "holdout modulus must be at least 2");
+        Self { modulus }
+    }
+
+    pub fn partition(&self, pubkey: &Pubkey) -> Partition {
+        let hash = Sha256::digest(pubkey.as_ref());
+        let hash = u64::from_le_bytes(hash[..8].try_into().unwrap());
+        if hash % self.modulus as u64 == 0 {
+            Partition::Test
+        } else {
+            Partition::Train
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partition {
+    Train,
+    Test,
+    All,
+}
+
+impl Partition {
+    pub const ALL: [Partition; 3] = [Partition::Train, Partition::Test, Partition::All];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Partition::Train => "train",
+            Partition::Test => "test",
+            Partition::All => "all",
+        }
+    }
+
+    /// Whether the account is part of this partition, every account is when there is no split.
+    pub fn contains(self, split: Option<&HoldoutSplit>, pubkey: &Pubkey) -> bool {
+        match (self, split) {
+            (Partition::All, _) | (_, None) => true,
+            (partition, Some(split)) => split.partition(pubkey) == partition,
+        }
+    }
+}
+
+impl Display for Partition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Partition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Partition::ALL
+            .into_iter()
+            .find(|partition| partition.name() == s)
+            .ok_or_else(|| format!("Unknown partition {s}"))
+    }
+}
+
+#[test]
+fn test_holdout_split() {
+    let split = HoldoutSplit::new(10);
+    let pubkeys = (0..10_000)
+        .map(|_| Pubkey::new_unique())
+        .collect::<Vec<_>>();
+    let test = pubkeys
+        .iter()
+        .filter(|pubkey| Partition::Test.contains(Some(&split), pubkey))
+        .count();
+    assert!((800..1200).contains(&test), "{test}");
+    // the split is part of the dictionary format, it must not change
+    let partitions = (0..8u8)
+        .map(|byte| split.partition(&Pubkey::new_from_array([byte; 32])))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        partitions,
+        [
+            Partition::Test,
+            Partition::Train,
+            Partition::Train,
+            Partition::Train,
+            Partition::Train,
+            Partition::Train,
+            Partition::Test,
+            Partition::Train,
+        ]
+    );
+    for pubkey in &pubkeys {
+        assert_ne!(
+            Partition::Test.contains(Some(&split), pubkey),
+            Partition::Train.contains(Some(&split), pubkey)
+        );
+        assert!(Partition::All.contains(Some(&split), pubkey));
+        assert!(Partition::Test.contains(None, pubkey));
+    }
+    // a modulus of 1 would leave no account to train on
+    assert!(std::panic::catch_unwind(|| HoldoutSplit::new(1)).is_err());
+}
@@ -0,0 +1,319 @@
+use {
+    crate::{
+        codec::{AccountCodec, CodecKind},
+        dictionary::{DictionaryBundle, DictionaryResolver},
+        dictionary_builder::{DictionaryBuilder, ZstdTrainingParams},
+        dictionary_key::{DictionaryKey, KeyScheme},
+        report::CsvRecord,
+        sampling::Samples,
+        size_selection::MIN_TRAINING_SAMPLES,
+    },
+    serde::Serialize,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    },
+};
+
+/// Accounts compressed by every combination of a sweep.
+#[derive(Default)]
+pub struct EvaluationAccounts {
+    /// Owner followed by the data of every account.
+    accounts: Samples,
+}
+
+impl EvaluationAccounts {
+    /// Sample of an account as stored in [`EvaluationAccounts::from_samples`].
+    pub fn sample(owner: &Pubkey, data: &[u8]) -> Vec<u8> {
+        [owner.as_ref(), data].concat()
+    }
+
+    /// Accounts from samples created with [`EvaluationAccounts::sample`].
+    pub const fn from_samples(accounts: Samples) -> Self {
+        Self { accounts }
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.sizes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.sizes.is_empty()
+    }
+
+    /// Data bytes of all the accounts.
+    pub fn data_len(&self) -> usize {
+        self.accounts.samples.len() - self.len() * std::mem::size_of::<Pubkey>()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Pubkey, &[u8])> {
+        self.accounts.iter().map(|sample| {
+            let (owner, data) = sample.split_at(std::mem::size_of::<Pubkey>());
+            (Pubkey::try_from(owner).unwrap(), data)
+        })
+    }
+}
+
+/// Builds a dictionary of `dictionary_size` bytes for `codec` for every key with enough samples.
+pub fn train_dictionaries(
+    samples: &HashMap<DictionaryKey, Samples>,
+    key_scheme: KeyScheme,
+    codec: CodecKind,
+    builder: DictionaryBuilder,
+    params: &ZstdTrainingParams,
+    dictionary_size: usize,
+) -> DictionaryBundle {
+    let mut bundle = DictionaryBundle::new(codec, vec![key_scheme], None);
+    let mut keys = samples.keys().copied().collect::<Vec<_>>();
+    // sorted so that dictionary ids are reproducible
+    keys.sort_unstable();
+    for key in keys {
+        let samples = &samples[&key];
+        if samples.sizes.len() < MIN_TRAINING_SAMPLES {
+            continue;
+        }
+        match builder.build(samples, dictionary_size, params) {
+            Ok(dictionary) => {
+                bundle.insert(key, dictionary, samples.stats());
+            }
+            Err(e) => {
+                log::warn!("building a {dictionary_size} bytes dictionary for {key:?} failed: {e}")
+            }
+        }
+    }
+    bundle
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvaluationStats {
+    pub accounts: u64,
+    pub accounts_with_dictionary: u64,
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+    pub compression_time: Duration,
+    pub decompression_time: Duration,
+    pub errors: u64,
+}
+
+/// Compresses and decompresses every account with the codec of its dictionary, or
+/// `default_codec` when none resolves.
+pub fn evaluate(
+    codecs: &mut DictionaryResolver<Box<dyn AccountCodec>>,
+    default_codec: &mut Box<dyn AccountCodec>,
+    accounts: &EvaluationAccounts,
+) -> EvaluationStats {
+    let mut stats = EvaluationStats::default();
+    let mut compressed = Vec::new();
+    let mut decompressed = Vec::new();
+    for (owner, data) in accounts.iter() {
+        stats.accounts += 1;
+        let codec = match codecs.resolve_for(&owner, data) {
+            Some(resolved) => {
+                stats.accounts_with_dictionary += 1;
+                resolved.dictionary
+            }
+            None => &mut *default_codec,
+        };
+        compressed.resize(codec.max_compressed_len(data.len()), 0);
+        let instant = Instant::now();
+        let len = match codec.compress_into(data, &mut compressed) {
+            Ok(len) => len,
+            Err(e) => {
+                log::error!("error {} compression {e:?}", codec.name());
+                stats.errors += 1;
+                continue;
+            }
+        };
+        stats.compression_time += instant.elapsed();
+
+        decompressed.resize(data.len(), 0);
+        let instant = Instant::now();
+        match codec.decompress_into(&compressed[..len], &mut decompressed) {
+            Ok(_) if decompressed == data => {}
+            Ok(_) => {
+                log::error!("{} decompressed data does not match", codec.name());
+                stats.errors += 1;
+                continue;
+            }
+            Err(e) => {
+                log::error!("error in {} decompression {e}", codec.name());
+                stats.errors += 1;
+                continue;
+            }
+        }
+        stats.decompression_time += instant.elapsed();
+        stats.uncompressed_bytes += data.len() as u64;
+        stats.compressed_bytes += len as u64;
+    }
+    stats
+}
+
+/// One combination of a sweep and its results.
+#[derive(Clone, Debug, Serialize)]
+pub struct SweepResult {
+    /// Owner prefix bits of the dictionary keys, `None` without dictionaries.
+    pub nb_bits: Option<u16>,
+    pub dictionary_size: Option<usize>,
+    pub builder: Option<DictionaryBuilder>,
+    pub codec: CodecKind,
+    pub level: Option<i32>,
+    pub dictionaries: usize,
+    pub dictionaries_bytes: usize,
+    pub accounts: u64,
+    pub accounts_with_dictionary: u64,
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+    pub compression_ratio: f64,
+    pub compression_ms: f64,
+    pub decompression_ms: f64,
+    pub errors: u64,
+}
+
+impl SweepResult {
+    pub fn new(
+        nb_bits: Option<u16>,
+        dictionary_size: Option<usize>,
+        builder: Option<DictionaryBuilder>,
+        level: Option<i32>,
+        dictionaries: &DictionaryBundle,
+        stats: &EvaluationStats,
+    ) -> Self {
+        Self {
+            nb_bits,
+            dictionary_size,
+            builder,
+            codec: dictionaries.codec,
+            level,
+            dictionaries: dictionaries.len(),
+            dictionaries_bytes: dictionaries.dictionaries_len(),
+            accounts: stats.accounts,
+            accounts_with_dictionary: stats.accounts_with_dictionary,
+            uncompressed_bytes: stats.uncompressed_bytes,
+            compressed_bytes: stats.compressed_bytes,
+            compression_ratio: stats.uncompressed_bytes as f64 / stats.compressed_bytes as f64,
+            compression_ms: stats.compression_time.as_secs_f64() * 1000.0,
+            decompression_ms: stats.decompression_time.as_secs_f64() * 1000.0,
+            errors: stats.errors,
+        }
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+impl CsvRecord for SweepResult {
+    const HEADER: &'static [&'static str] = &[
+        "nb_bits",
+        "dictionary_size",
+        "builder",
+        "codec",
+        "level",
+        "dictionaries",
+        "dictionaries_bytes",
+        "accounts",
+        "accounts_with_dictionary",
+        "uncompressed_bytes",
+        "compressed_bytes",
+        "compression_ratio",
+        "compression_ms",
+        "decompression_ms",
+        "errors",
+    ];
+
+    fn record(&self) -> Vec<String> {
+        vec![
+            optional(self.nb_bits),
+            optional(self.dictionary_size),
+            optional(self.builder),
+            self.codec.to_string(),
+            optional(self.level),
+            self.dictionaries.to_string(),
+            self.dictionaries_bytes.to_string(),
+            self.accounts.to_string(),
+            self.accounts_with_dictionary.to_string(),
+            self.uncompressed_bytes.to_string(),
+            self.compressed_bytes.to_string(),
+            format!("{:.4}", self.compression_ratio),
+            format!("{:.3}", self.compression_ms),
+            format!("{:.3}", self.decompression_ms),
+            self.errors.to_string(),
+        ]
+    }
+}
+
+#[test]
+fn test_sweep_evaluation() {
+    use crate::{dictionary_key::OwnerKeyScheme, report::ReportFormat};
+
+    let owners = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let mut evaluation = Samples::default();
+    let mut training = HashMap::<_, Samples>::new();
+    let key_scheme = KeyScheme::new(
+        OwnerKeyScheme::OwnerPrefixBits { nb_bits: 256 },
+        crate::dictionary_key::AccountKeyScheme::None,
+    );
+    for i in 0..200u32 {
+        let owner = owners[i as usize % 2];
+        let mut data = format!("account {} of owner {owner}", i % 7).repeat(4);
+        data.push_str(&i.to_string());
+        training
+            .entry(key_scheme.key_for(&owner, data.as_bytes()))
+            .or_default()
+            .add(data.as_bytes());
+        evaluation.add(&EvaluationAccounts::sample(&owner, data.as_bytes()));
+    }
+    let accounts = EvaluationAccounts::from_samples(evaluation);
+    assert_eq!(accounts.len(), 200);
+    assert_eq!(
+        accounts
+            .iter()
+            .filter(|(owner, _)| *owner == owners[0])
+            .count(),
+        100
+    );
+
+    let bundle = train_dictionaries(
+        &training,
+        key_scheme,
+        CodecKind::Zstd,
+        DictionaryBuilder::Zstd,
+        &ZstdTrainingParams::default(),
+        1024,
+    );
+    assert_eq!(bundle.len(), 2);
+    let mut codecs = DictionaryResolver::from_bundle(&bundle, |entry| {
+        CodecKind::Zstd.create(None, Some(&entry.dictionary))
+    })
+    .unwrap();
+    let mut default_codec = CodecKind::Zstd.create(None, None).unwrap();
+    let stats = evaluate(&mut codecs, &mut default_codec, &accounts);
+    assert_eq!(stats.accounts, 200);
+    assert_eq!(stats.accounts_with_dictionary, 200);
+    assert_eq!(stats.errors, 0);
+    assert_eq!(stats.uncompressed_bytes, accounts.data_len() as u64);
+    assert!(stats.compressed_bytes < stats.uncompressed_bytes);
+
+    let results = vec![SweepResult::new(
+        Some(256),
+        Some(1024),
+        Some(DictionaryBuilder::Zstd),
+        Some(3),
+        &bundle,
+        &stats,
+    )];
+    let mut csv = Vec::new();
+    ReportFormat::Csv.write(&mut csv, &results).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().count(), 2);
+    assert!(csv
+        .lines()
+        .nth(1)
+        .unwrap()
+        .starts_with("256,1024,zstd,zstd,3,2,"));
+    let mut json = Vec::new();
+    ReportFormat::Json.write(&mut json, &results).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json[0]["codec"], "zstd");
+}
@@ -0,0 +1,335 @@
+use {
+    crate::{
+        append_vec::{AppendVec, StoredAccountMeta},
+        append_vec_iter,
+        breakdown::{BreakdownStats, CompressionBreakdown},
+        codec::{AccountCodec, CodecKind},
+        compression_stats::CompressionStats,
+        dictionary::{DictionaryBundle, DictionaryResolver},
+        parallel::AppendVecFolder,
+        split::{HoldoutSplit, Partition},
+    },
+    std::time::Instant,
+};
+
+/// Whether the account is compressed by the tester.
+pub fn is_evaluated(
+    account: &StoredAccountMeta,
+    split: Option<HoldoutSplit>,
+    partition: Partition,
+) -> bool {
+    account.meta.data_len >= 64 && partition.contains(split.as_ref(), &account.meta.pubkey)
+}
+
+/// Results of the accounts evaluated by one or several threads.
+pub struct TesterStats {
+    pub accounts: u64,
+    pub accounts_with_dict: usize,
+    pub accounts_without_dict: usize,
+    /// Accounts resolved with each key scheme.
+    pub accounts_per_level: Vec<usize>,
+    pub compression_errors: usize,
+    pub decompression_errors: usize,
+    /// Accounts left out of the breakdown because compressing them without dictionary failed.
+    pub comparison_errors: usize,
+    pub compression: CompressionStats,
+    pub breakdown: CompressionBreakdown,
+}
+
+impl TesterStats {
+    pub fn new(key_schemes: usize) -> Self {
+        Self {
+            accounts: 0,
+            accounts_with_dict: 0,
+            accounts_without_dict: 0,
+            accounts_per_level: vec![0; key_schemes],
+            compression_errors: 0,
+            decompression_errors: 0,
+            comparison_errors: 0,
+            compression: CompressionStats::default(),
+            breakdown: CompressionBreakdown::default(),
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.accounts += other.accounts;
+        self.accounts_with_dict += other.accounts_with_dict;
+        self.accounts_without_dict += other.accounts_without_dict;
+        for (accounts, other) in self
+            .accounts_per_level
+            .iter_mut()
+            .zip(&other.accounts_per_level)
+        {
+            *accounts += other;
+        }
+        self.compression_errors += other.compression_errors;
+        self.decompression_errors += other.decompression_errors;
+        self.comparison_errors += other.comparison_errors;
+        self.compression.merge(&other.compression);
+        self.breakdown.merge(&other.breakdown);
+    }
+}
+
+/// Compresses and decompresses the accounts of the append vecs given to one thread.
+pub struct TesterFolder {
+    /// One codec per dictionary so that dictionaries are prepared only once.
+    codecs: DictionaryResolver<Box<dyn AccountCodec>>,
+    default_codec: Box<dyn AccountCodec>,
+    split: Option<HoldoutSplit>,
+    partition: Partition,
+    /// Also compress the accounts with dictionary without it.
+    breakdown: bool,
+    compress_out_buffer: Vec<u8>,
+    plain_out_buffer: Vec<u8>,
+    stats: TesterStats,
+}
+
+impl TesterFolder {
+    pub fn new(
+        dictionary: &DictionaryBundle,
+        codec: CodecKind,
+        level: Option<i32>,
+        partition: Partition,
+        breakdown: bool,
+    ) -> anyhow::Result<Self> {
+        let codecs = if codec.supports_dictionary() {
+            DictionaryResolver::from_bundle(dictionary, |entry| {
+                codec.create(level, Some(&entry.dictionary))
+            })?
+        } else {
+            DictionaryResolver::new(dictionary.key_schemes.clone())
+        };
+        Ok(Self {
+            codecs,
+            default_codec: codec.create(level, None)?,
+            split: dictionary.split,
+            partition,
+            breakdown,
+            compress_out_buffer: vec![0; 16 * 1024 * 1024], // 16 MB buffer
+            plain_out_buffer: Vec::new(),
+            stats: TesterStats::new(dictionary.key_schemes.len()),
+        })
+    }
+
+    pub fn into_stats(self) -> TesterStats {
+        self.stats
+    }
+}
+
+impl AppendVecFolder for TesterFolder {
+    fn fold(&mut self, append_vec: AppendVec) -> anyhow::Result<()> {
+        let stats = &mut self.stats;
+        for handle in append_vec_iter(&append_vec) {
+            let stored = handle.access().unwrap();
+            if !is_evaluated(&stored, self.split, self.partition) {
+                continue;
+            }
+            stats.accounts += 1;
+
+            let (key, codec) = match self.codecs.resolve(&stored) {
+                Some(resolved) => {
+                    stats.accounts_with_dict += 1;
+                    stats.accounts_per_level[resolved.level] += 1;
+                    (Some(resolved.key), resolved.dictionary)
+                }
+                None => {
+                    stats.accounts_without_dict += 1;
+                    (None, &mut self.default_codec)
+                }
+            };
+
+            let compression_time;
+            let max_compressed_len = codec.max_compressed_len(stored.data.len());
+            if self.compress_out_buffer.len() < max_compressed_len {
+                self.compress_out_buffer.resize(max_compressed_len, 0);
+            }
+            let instant = Instant::now();
+            let len = match codec.compress_into(stored.data, &mut self.compress_out_buffer) {
+                Ok(len) => {
+                    compression_time = instant.elapsed();
+                    len
+                }
+                Err(e) => {
+                    log::error!("error {} compression {e:?}", codec.name());
+                    stats.compression_errors += 1;
+                    continue;
+                }
+            };
+
+            let mut output = vec![0; stored.meta.data_len as usize];
+            let instant = Instant::now();
+            match codec.decompress_into(&self.compress_out_buffer[..len], &mut output) {
+                Ok(_) => {
+                    stats.compression.add(
+                        stored.data.len(),
+                        len,
+                        compression_time,
+                        instant.elapsed(),
+                    );
+                }
+                Err(e) => {
+                    log::error!(
+                        "error in {} decompression {e} for decompressing {len} bytes",
+                        codec.name()
+                    );
+                    stats.decompression_errors += 1;
+                    continue;
+                }
+            }
+            assert_eq!(output, stored.data);
+
+            if self.breakdown {
+                let mut account = BreakdownStats {
+                    accounts: 1,
+                    raw_bytes: stored.data.len() as u64,
+                    compressed_bytes: len as u64,
+                    plain_compressed_bytes: len as u64,
+                    compression_time,
+                    plain_compression_time: compression_time,
+                };
+                if key.is_some() {
+                    let default_codec = &mut self.default_codec;
+                    self.plain_out_buffer
+                        .resize(default_codec.max_compressed_len(stored.data.len()), 0);
+                    let instant = Instant::now();
+                    match default_codec.compress_into(stored.data, &mut self.plain_out_buffer) {
+                        Ok(plain_len) => {
+                            account.plain_compression_time = instant.elapsed();
+                            account.plain_compressed_bytes = plain_len as u64;
+                        }
+                        Err(e) => {
+                            log::error!("error {} compression {e:?}", default_codec.name());
+                            stats.comparison_errors += 1;
+                            continue;
+                        }
+                    }
+                }
+                stats
+                    .breakdown
+                    .add(key, stored.account_meta.owner, &account);
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.stats.merge(&other.stats);
+    }
+}
+
+#[test]
+fn test_tester_folder_threads() {
+    use {
+        crate::{
+            append_vec::test_append_vec,
+            dictionary::TrainingStats,
+            dictionary_key::{AccountKeyScheme, KeyScheme, OwnerKeyScheme},
+            parallel::par_fold_append_vecs,
+        },
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    let owner_key_scheme = KeyScheme::new(
+        OwnerKeyScheme::OwnerPrefixBits {
+            nb_bits: crate::partial_pubkey_by_bits::MAX_NB_BITS,
+        },
+        AccountKeyScheme::None,
+    );
+    let mut dictionary = DictionaryBundle::new(
+        CodecKind::Lz4Flex,
+        vec![owner_key_scheme, KeyScheme::GLOBAL],
+        None,
+    );
+    dictionary.split = Some(HoldoutSplit::new(3));
+    let owner = Pubkey::new_unique();
+    let other_owner = Pubkey::new_unique();
+    let data = (0..200u8).map(|i| i % 7).collect::<Vec<_>>();
+    dictionary.insert(
+        owner_key_scheme.key_for(&owner, &[]),
+        data.clone(),
+        TrainingStats::default(),
+    );
+
+    // evaluated accounts of both owners, with and without dictionary, and too small accounts
+    let slots = (0..24u64)
+        .map(|slot| {
+            let (owner, len) = match slot % 3 {
+                0 => (owner, data.len()),
+                1 => (other_owner, 100),
+                _ => (owner, 32),
+            };
+            (0..slot)
+                .map(|write_version| (Pubkey::new_unique(), write_version, 1, owner, &data[..len]))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let append_vecs = || {
+        Box::new(
+            slots
+                .iter()
+                .enumerate()
+                .map(|(slot, accounts)| Ok(test_append_vec(slot as u64, 0, accounts)))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )
+    };
+    let fold = |threads| {
+        par_fold_append_vecs(
+            append_vecs(),
+            || TesterFolder::new(&dictionary, CodecKind::Lz4Flex, None, Partition::Test, true),
+            threads,
+        )
+        .unwrap()
+        .into_stats()
+    };
+
+    let single = fold(1);
+    assert!(single.accounts_with_dict > 0);
+    assert!(single.accounts_without_dict > 0);
+    assert_eq!(
+        single.accounts,
+        (single.accounts_with_dict + single.accounts_without_dict) as u64
+    );
+    assert_eq!(single.accounts_per_level[1], 0);
+    for threads in [2, 4] {
+        let stats = fold(threads);
+        assert_eq!(stats.accounts, single.accounts);
+        assert_eq!(stats.accounts_with_dict, single.accounts_with_dict);
+        assert_eq!(stats.accounts_without_dict, single.accounts_without_dict);
+        assert_eq!(stats.accounts_per_level, single.accounts_per_level);
+        assert_eq!(stats.compression_errors, 0);
+        assert_eq!(stats.decompression_errors, 0);
+        assert_eq!(stats.comparison_errors, 0);
+        assert_eq!(stats.compression.accounts, single.compression.accounts);
+        assert_eq!(
+            stats.compression.uncompressed_bytes,
+            single.compression.uncompressed_bytes
+        );
+        assert_eq!(
+            stats.compression.compressed_bytes,
+            single.compression.compressed_bytes
+        );
+        assert!(stats
+            .compression
+            .size_buckets()
+            .eq(single.compression.size_buckets()));
+        let counters = |stats: &TesterStats| {
+            stats
+                .breakdown
+                .rows(2)
+                .into_iter()
+                .map(|row| {
+                    (
+                        row.group,
+                        row.name,
+                        row.accounts,
+                        row.raw_bytes,
+                        row.compressed_bytes,
+                        row.plain_compressed_bytes,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(counters(&stats), counters(&single));
+    }
+}
@@ -1,8 +1,9 @@
 use {
     crate::{
-        deserialize_from, parse_append_vec_name, AccountsDbFields, AppendVec, AppendVecIterator,
-        DeserializableVersionedBank, ReadProgressTracking, SerializableAccountStorageEntry,
-        SnapshotError, SnapshotExtractor, SnapshotResult, SNAPSHOTS_DIR,
+        deserialize_from, manifest::SnapshotManifest, parse_append_vec_name, AccountsDbFields,
+        AppendVec, AppendVecIterator, DeserializableVersionedBank, ReadProgressTracking,
+        SerializableAccountStorageEntry, SnapshotError, SnapshotExtractor, SnapshotResult,
+        SNAPSHOTS_DIR,
     },
     itertools::Itertools,
     log::info,
@@ -20,12 +21,21 @@ use {
 pub struct UnpackedSnapshotExtractor {
     root: PathBuf,
     accounts_db_fields: AccountsDbFields<SerializableAccountStorageEntry>,
+    manifest: SnapshotManifest,
 }
 
 impl SnapshotExtractor for UnpackedSnapshotExtractor {
     fn iter(&mut self) -> AppendVecIterator<'_> {
         Box::new(self.unboxed_iter())
     }
+
+    fn accounts_db_fields(&self) -> &AccountsDbFields<SerializableAccountStorageEntry> {
+        &self.accounts_db_fields
+    }
+
+    fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
 }
 
 impl UnpackedSnapshotExtractor {
@@ -60,13 +70,14 @@ impl UnpackedSnapshotExtractor {
 
         let pre_unpack = Instant::now();
         let versioned_bank: DeserializableVersionedBank = deserialize_from(&mut snapshot_file)?;
-        drop(versioned_bank);
         let versioned_bank_post_time = Instant::now();
 
         let accounts_db_fields: AccountsDbFields<SerializableAccountStorageEntry> =
             deserialize_from(&mut snapshot_file)?;
         let accounts_db_fields_post_time = Instant::now();
         drop(snapshot_file);
+        let manifest = SnapshotManifest::new(&versioned_bank, &accounts_db_fields);
+        drop(versioned_bank);
 
         info!(
             "Read bank fields in {:?}",
@@ -80,6 +91,7 @@ impl UnpackedSnapshotExtractor {
         Ok(UnpackedSnapshotExtractor {
             root: path.to_path_buf(),
             accounts_db_fields,
+            manifest,
         })
     }
 
@@ -120,6 +132,7 @@ impl UnpackedSnapshotExtractor {
             path,
             known_vec.accounts_current_len,
             slot,
+            id,
         )?)
     }
 }